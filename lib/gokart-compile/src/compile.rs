@@ -119,13 +119,22 @@ impl<'a> Compile<'a> for &'a Exp {
                 right.compile(ctx, env);
                 ctx.code[gt_idx] = VOpCode::Goto(ctx.code.cur_label())
             }
-            Exp::Case(cond, branches) => {
+            Exp::Case(cond, branches, default) => {
                 ctx.code.push_back(VOpCode::Push);
                 cond.compile(ctx, env.clone());
 
                 let sw_idx = ctx.code.push_dummies(branches.len());
                 let mut gt_idxs = Vec::new();
 
+                // no switch is taken, so the environment is still on the stack
+                if let Some((pat, exp)) = default {
+                    ctx.code.push_back(VOpCode::Cons);
+                    exp.compile(ctx, env.push_front(EnvUnit::Con(pat)));
+                    if !branches.is_empty() {
+                        gt_idxs.push(ctx.code.push_dummy());
+                    }
+                }
+
                 for (idx, (tag, pat, exp)) in branches.iter().enumerate() {
                     ctx.code[sw_idx + idx] = VOpCode::Switch(*tag, ctx.code.cur_label());
                     let new_env = env.push_front(EnvUnit::Con(pat));
@@ -352,6 +361,7 @@ mod tests {
                     (0, Pat::Empty, case1),
                     (1, Pat::Pair(pvar(2), pvar(3)), case2),
                 ],
+                None,
             )
             .ptr(),
         );
@@ -379,6 +389,45 @@ mod tests {
         )
     }
 
+    #[test]
+    fn ok_adt_default() {
+        // data List = Nil | Cons (Int, List)
+        //              ^     ^
+        // id:          0     1
+
+        // \s -> case s of Nil () -> Nil () | t -> t
+        let case1 = Exp::Con(0, Exp::Empty.ptr());
+        let exp = Exp::Abs(
+            pvar_(1),
+            Exp::Case(
+                evar(1),
+                vec![(0, Pat::Empty, case1)],
+                Some((pvar_(2), evar(2))),
+            )
+            .ptr(),
+        );
+
+        assert_eq!(
+            compile(&exp),
+            [
+                Cur(2),
+                Stop,
+                // lbl:2
+                Push,
+                Acc(0),
+                Switch(0, 8),
+                Cons,
+                Acc(0),
+                Goto(10),
+                // lbl:8
+                Clear,
+                Pack(0),
+                // lbl:10
+                Return,
+            ]
+        )
+    }
+
     #[test]
     fn ok_local_def() {
         // let a = 5 in a * a
@@ -1,6 +1,6 @@
 use crate::Loc;
 use derive_new::new;
-use std::ops::Deref;
+use std::{collections::HashSet, ops::Deref};
 
 #[derive(Debug, new)]
 pub struct IntLit<'a> {
@@ -16,7 +16,7 @@ pub struct DoubleLit<'a> {
 
 #[derive(Debug, new)]
 pub struct StrLit<'a> {
-    pub val: &'a str,
+    pub val: String,
     pub loc: Loc<'a>,
 }
 
@@ -31,6 +31,7 @@ pub enum Lit<'a> {
     Int(IntLit<'a>),
     Double(DoubleLit<'a>),
     Str(StrLit<'a>),
+    Bool(BoolLit<'a>),
 }
 
 impl<'a> Lit<'a> {
@@ -39,6 +40,7 @@ impl<'a> Lit<'a> {
             Lit::Int(lit) => lit.loc,
             Lit::Double(lit) => lit.loc,
             Lit::Str(lit) => lit.loc,
+            Lit::Bool(lit) => lit.loc,
         }
     }
 }
@@ -53,6 +55,8 @@ pub struct Ast<'a> {
 #[derive(Debug)]
 pub enum Def<'a> {
     TypeDef(TypeDef<'a>),
+    OprDef(OprDef<'a>),
+    ValDef(ValDef<'a>),
 }
 
 #[derive(Debug, new)]
@@ -91,17 +95,98 @@ impl<'a> Deref for Name<'a> {
 #[derive(Debug, new)]
 pub struct Con<'a> {
     pub name: Name<'a>,
-    pub args: Vec<Name<'a>>,
+    pub args: Vec<TypeExpr<'a>>,
     pub loc: Loc<'a>,
 }
 
 #[derive(Debug, new)]
 pub struct TypeDef<'a> {
     pub name: Name<'a>,
+    pub params: Vec<Name<'a>>,
     pub cons: Vec<Con<'a>>,
     pub loc: Loc<'a>,
 }
 
+#[derive(Debug, new)]
+pub struct ConTypeExpr<'a> {
+    pub name: Name<'a>,
+    pub args: Vec<TypeExpr<'a>>,
+    pub loc: Loc<'a>,
+}
+
+#[derive(Debug, new)]
+pub struct EmptyTypeExpr<'a> {
+    pub loc: Loc<'a>,
+}
+
+#[derive(Debug, new)]
+pub struct PairTypeExpr<'a> {
+    pub left: TypeExprPtr<'a>,
+    pub right: TypeExprPtr<'a>,
+    pub loc: Loc<'a>,
+}
+
+#[derive(Debug, new)]
+pub struct FuncTypeExpr<'a> {
+    pub from: TypeExprPtr<'a>,
+    pub into: TypeExprPtr<'a>,
+    pub loc: Loc<'a>,
+}
+
+#[derive(Debug)]
+pub enum TypeExpr<'a> {
+    Empty(EmptyTypeExpr<'a>),
+    Var(Name<'a>),
+    Con(ConTypeExpr<'a>),
+    Pair(PairTypeExpr<'a>),
+    Func(FuncTypeExpr<'a>),
+}
+
+impl<'a> TypeExpr<'a> {
+    #[inline]
+    pub fn ptr(self) -> TypeExprPtr<'a> {
+        Box::new(self)
+    }
+
+    pub fn loc(&self) -> Loc<'a> {
+        match self {
+            TypeExpr::Empty(expr) => expr.loc,
+            TypeExpr::Var(expr) => expr.loc,
+            TypeExpr::Con(expr) => expr.loc,
+            TypeExpr::Pair(expr) => expr.loc,
+            TypeExpr::Func(expr) => expr.loc,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assoc {
+    Left,
+    Right,
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, new)]
+pub struct Fixity {
+    pub assoc: Assoc,
+    pub prec: u8,
+}
+
+#[derive(Debug, new)]
+pub struct OprDef<'a> {
+    pub fixity: Fixity,
+    pub name: Name<'a>,
+    pub term: Term<'a>,
+    pub loc: Loc<'a>,
+}
+
+#[derive(Debug, new)]
+pub struct ValDef<'a> {
+    pub name: Name<'a>,
+    pub term: Term<'a>,
+    pub loc: Loc<'a>,
+}
+
 #[derive(Debug, new)]
 pub struct ConTerm<'a> {
     pub name: Name<'a>,
@@ -117,6 +202,25 @@ pub struct Opr<'a> {
     pub loc: Loc<'a>,
 }
 
+// `-term`, whether it negates an integer or a double is known after the verify step
+#[derive(Debug, new)]
+pub struct Neg<'a> {
+    pub term: TermPtr<'a>,
+    pub loc: Loc<'a>,
+}
+
+// the offsets of the negations of doubles, the rest negate integers
+pub type DoubleNegs = HashSet<usize>;
+
+// an operation with some of the operands missing, e.g. `(+)`, `(+ 1)` or `(10 -)`
+#[derive(Debug, new)]
+pub struct Section<'a> {
+    pub left: Option<TermPtr<'a>>,
+    pub name: Name<'a>,
+    pub right: Option<TermPtr<'a>>,
+    pub loc: Loc<'a>,
+}
+
 #[derive(Debug, new)]
 pub struct App<'a> {
     pub head: TermPtr<'a>,
@@ -134,15 +238,15 @@ pub struct Cond<'a> {
 
 #[derive(Debug, new)]
 pub struct Abs<'a> {
-    pub arg: Name<'a>,
+    pub arg: Tpl<'a>,
     pub body: TermPtr<'a>,
     pub loc: Loc<'a>,
 }
 
 #[derive(Debug, new)]
 pub struct Branch<'a> {
-    pub con: Name<'a>,
     pub tpl: Tpl<'a>,
+    pub guard: Option<Term<'a>>,
     pub body: Term<'a>,
     pub loc: Loc<'a>,
 }
@@ -170,6 +274,21 @@ pub struct Letrec<'a> {
     pub loc: Loc<'a>,
 }
 
+// evaluates the first term only for its side effects
+#[derive(Debug, new)]
+pub struct Seq<'a> {
+    pub first: TermPtr<'a>,
+    pub second: TermPtr<'a>,
+    pub loc: Loc<'a>,
+}
+
+#[derive(Debug, new)]
+pub struct AnnTerm<'a> {
+    pub term: TermPtr<'a>,
+    pub ty: TypeExpr<'a>,
+    pub loc: Loc<'a>,
+}
+
 #[derive(Debug)]
 pub enum Term<'a> {
     Empty(EmptyTerm<'a>),
@@ -178,12 +297,16 @@ pub enum Term<'a> {
     Lit(Lit<'a>),
     Con(ConTerm<'a>),
     Opr(Opr<'a>),
+    Section(Section<'a>),
+    Neg(Neg<'a>),
     App(App<'a>),
     Cond(Cond<'a>),
     Abs(Abs<'a>),
     Case(Case<'a>),
     Let(Let<'a>),
     Letrec(Letrec<'a>),
+    Seq(Seq<'a>),
+    Ann(AnnTerm<'a>),
 }
 
 impl<'a> Term<'a> {
@@ -200,12 +323,16 @@ impl<'a> Term<'a> {
             Term::Lit(term) => term.loc(),
             Term::Con(term) => term.loc,
             Term::Opr(term) => term.loc,
+            Term::Section(term) => term.loc,
+            Term::Neg(term) => term.loc,
             Term::App(term) => term.loc,
             Term::Cond(term) => term.loc,
             Term::Abs(term) => term.loc,
             Term::Case(term) => term.loc,
             Term::Let(term) => term.loc,
             Term::Letrec(term) => term.loc,
+            Term::Seq(term) => term.loc,
+            Term::Ann(term) => term.loc,
         }
     }
 }
@@ -235,12 +362,35 @@ pub struct EmptyTpl<'a> {
     pub loc: Loc<'a>,
 }
 
+#[derive(Debug, new)]
+pub struct WildTpl<'a> {
+    pub loc: Loc<'a>,
+}
+
+#[derive(Debug, new)]
+pub struct ConTpl<'a> {
+    pub name: Name<'a>,
+    pub body: TplPtr<'a>,
+    pub loc: Loc<'a>,
+}
+
+#[derive(Debug, new)]
+pub struct AnnTpl<'a> {
+    pub tpl: TplPtr<'a>,
+    pub ty: TypeExpr<'a>,
+    pub loc: Loc<'a>,
+}
+
 #[derive(Debug)]
 pub enum Tpl<'a> {
     Empty(EmptyTpl<'a>),
     Var(Name<'a>),
+    Wild(WildTpl<'a>),
+    Lit(Lit<'a>),
     Pair(PairTpl<'a>),
     As(AsTpl<'a>),
+    Con(ConTpl<'a>),
+    Ann(AnnTpl<'a>),
 }
 
 impl<'a> Tpl<'a> {
@@ -253,11 +403,16 @@ impl<'a> Tpl<'a> {
         match self {
             Tpl::Empty(tpl) => tpl.loc,
             Tpl::Var(tpl) => tpl.loc,
+            Tpl::Wild(tpl) => tpl.loc,
+            Tpl::Lit(tpl) => tpl.loc(),
             Tpl::Pair(tpl) => tpl.loc,
             Tpl::As(tpl) => tpl.loc,
+            Tpl::Con(tpl) => tpl.loc,
+            Tpl::Ann(tpl) => tpl.loc,
         }
     }
 }
 
 pub type TermPtr<'a> = Box<Term<'a>>;
 pub type TplPtr<'a> = Box<Tpl<'a>>;
+pub type TypeExprPtr<'a> = Box<TypeExpr<'a>>;
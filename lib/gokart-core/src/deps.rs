@@ -0,0 +1,247 @@
+use crate::{Ast, Def, Name, Term, Tpl, VarName};
+use std::collections::HashMap;
+
+// a top-level definition of a value or an operation
+#[derive(Debug, Clone, Copy)]
+pub struct Binding<'t, 'a> {
+    pub name: &'t Name<'a>,
+    pub term: &'t Term<'a>,
+}
+
+// definitions that have to be checked together,
+// `rec` is set when some of them refer to the group itself
+#[derive(Debug, PartialEq, Eq)]
+pub struct Group {
+    pub idxs: Vec<usize>,
+    pub rec: bool,
+}
+
+impl<'a> Ast<'a> {
+    pub fn bindings(&self) -> Vec<Binding<'_, 'a>> {
+        self.defs
+            .iter()
+            .filter_map(|def| match def {
+                Def::TypeDef(_) => None,
+                Def::OprDef(def) => Some(Binding {
+                    name: &def.name,
+                    term: &def.term,
+                }),
+                Def::ValDef(def) => Some(Binding {
+                    name: &def.name,
+                    term: &def.term,
+                }),
+            })
+            .collect()
+    }
+}
+
+fn bind<'a>(tpl: &Tpl<'a>, bound: &mut Vec<VarName<'a>>) {
+    match tpl {
+        Tpl::Empty(_) | Tpl::Wild(_) | Tpl::Lit(_) => (),
+        Tpl::Var(name) => bound.push(name.val),
+        Tpl::Pair(tpl) => {
+            bind(&tpl.left, bound);
+            bind(&tpl.right, bound);
+        }
+        Tpl::As(tpl) => {
+            bound.push(tpl.name.val);
+            bind(&tpl.tpl, bound);
+        }
+        Tpl::Con(tpl) => bind(&tpl.body, bound),
+        Tpl::Ann(tpl) => bind(&tpl.tpl, bound),
+    }
+}
+
+fn refs_under<'a>(
+    tpl: &Tpl<'a>,
+    term: &Term<'a>,
+    bound: &mut Vec<VarName<'a>>,
+    res: &mut Vec<VarName<'a>>,
+) {
+    let len = bound.len();
+    bind(tpl, bound);
+    refs(term, bound, res);
+    bound.truncate(len);
+}
+
+// the names the term refers to, except the ones bound inside it
+fn refs<'a>(term: &Term<'a>, bound: &mut Vec<VarName<'a>>, res: &mut Vec<VarName<'a>>) {
+    match term {
+        Term::Empty(_) | Term::Lit(_) => (),
+        Term::Var(name) => {
+            if !bound.contains(&name.val) {
+                res.push(name.val);
+            }
+        }
+        Term::Pair(term) => {
+            refs(&term.left, bound, res);
+            refs(&term.right, bound, res);
+        }
+        Term::Con(term) => refs(&term.body, bound, res),
+        Term::Opr(term) => {
+            if !bound.contains(&term.name.val) {
+                res.push(term.name.val);
+            }
+            refs(&term.left, bound, res);
+            refs(&term.right, bound, res);
+        }
+        Term::Section(term) => {
+            if !bound.contains(&term.name.val) {
+                res.push(term.name.val);
+            }
+            for term in [&term.left, &term.right].into_iter().flatten() {
+                refs(term, bound, res);
+            }
+        }
+        Term::Neg(term) => refs(&term.term, bound, res),
+        Term::App(term) => {
+            refs(&term.head, bound, res);
+            refs(&term.body, bound, res);
+        }
+        Term::Cond(term) => {
+            refs(&term.cond, bound, res);
+            refs(&term.left, bound, res);
+            refs(&term.right, bound, res);
+        }
+        Term::Abs(term) => refs_under(&term.arg, &term.body, bound, res),
+        Term::Case(term) => {
+            refs(&term.cond, bound, res);
+            for branch in term.branches.iter() {
+                if let Some(guard) = &branch.guard {
+                    refs_under(&branch.tpl, guard, bound, res);
+                }
+                refs_under(&branch.tpl, &branch.body, bound, res);
+            }
+        }
+        Term::Let(term) => {
+            refs(&term.term, bound, res);
+            refs_under(&term.tpl, &term.body, bound, res);
+        }
+        Term::Letrec(term) => {
+            refs_under(&term.tpl, &term.term, bound, res);
+            refs_under(&term.tpl, &term.body, bound, res);
+        }
+        Term::Seq(term) => {
+            refs(&term.first, bound, res);
+            refs(&term.second, bound, res);
+        }
+        Term::Ann(term) => refs(&term.term, bound, res),
+    }
+}
+
+struct Tarjan {
+    edges: Vec<Vec<usize>>,
+    idxs: Vec<Option<usize>>,
+    lows: Vec<usize>,
+    stack: Vec<usize>,
+    on_stack: Vec<bool>,
+    cnt: usize,
+    res: Vec<Group>,
+}
+
+impl Tarjan {
+    fn visit(&mut self, v: usize) {
+        self.idxs[v] = Some(self.cnt);
+        self.lows[v] = self.cnt;
+        self.cnt += 1;
+        self.stack.push(v);
+        self.on_stack[v] = true;
+
+        for w in self.edges[v].clone() {
+            match self.idxs[w] {
+                None => {
+                    self.visit(w);
+                    self.lows[v] = self.lows[v].min(self.lows[w]);
+                }
+                Some(idx) if self.on_stack[w] => self.lows[v] = self.lows[v].min(idx),
+                Some(_) => (),
+            }
+        }
+
+        if Some(self.lows[v]) == self.idxs[v] {
+            let mut idxs = Vec::new();
+            while let Some(w) = self.stack.pop() {
+                self.on_stack[w] = false;
+                idxs.push(w);
+                if w == v {
+                    break;
+                }
+            }
+            idxs.sort();
+
+            let rec = idxs.len() > 1 || self.edges[v].contains(&v);
+            self.res.push(Group { idxs, rec });
+        }
+    }
+}
+
+// splits the definitions into groups, every group comes after the ones it refers to
+pub fn groups(bindings: &[Binding]) -> Vec<Group> {
+    let names = bindings
+        .iter()
+        .map(|binding| vec![binding.name.val])
+        .collect::<Vec<_>>();
+    let terms = bindings
+        .iter()
+        .map(|binding| binding.term)
+        .collect::<Vec<_>>();
+
+    sort(&names, &terms)
+}
+
+// the same for local definitions, which may bind several names with one pattern
+pub fn tpl_groups(parts: &[(Tpl, Term)]) -> Vec<Group> {
+    let names = parts
+        .iter()
+        .map(|(tpl, _)| {
+            let mut res = Vec::new();
+            bind(tpl, &mut res);
+            res
+        })
+        .collect::<Vec<_>>();
+    let terms = parts.iter().map(|(_, term)| term).collect::<Vec<_>>();
+
+    sort(&names, &terms)
+}
+
+fn sort<'a>(names: &[Vec<VarName<'a>>], terms: &[&Term<'a>]) -> Vec<Group> {
+    let names = names
+        .iter()
+        .enumerate()
+        .flat_map(|(idx, names)| names.iter().map(move |name| (*name, idx)))
+        .collect::<HashMap<_, _>>();
+
+    let edges = terms
+        .iter()
+        .map(|term| {
+            let mut res = Vec::new();
+            refs(term, &mut Vec::new(), &mut res);
+
+            let mut edges = res
+                .into_iter()
+                .filter_map(|name| names.get(name).copied())
+                .collect::<Vec<_>>();
+            edges.sort();
+            edges.dedup();
+            edges
+        })
+        .collect::<Vec<_>>();
+
+    let len = terms.len();
+    let mut tarjan = Tarjan {
+        edges,
+        idxs: vec![None; len],
+        lows: vec![0; len],
+        stack: Vec::new(),
+        on_stack: vec![false; len],
+        cnt: 0,
+        res: Vec::new(),
+    };
+
+    for v in 0..len {
+        if tarjan.idxs[v].is_none() {
+            tarjan.visit(v);
+        }
+    }
+    tarjan.res
+}
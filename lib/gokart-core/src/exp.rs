@@ -14,7 +14,8 @@ pub enum Exp {
     App(ExpPtr, ExpPtr),
     Abs(Pat, ExpPtr),
     Cond(ExpPtr, ExpPtr, ExpPtr),
-    Case(ExpPtr, Vec<(Tag, Pat, Exp)>),
+    // the last one is taken when none of the tags match
+    Case(ExpPtr, Vec<(Tag, Pat, Exp)>, Option<(Pat, ExpPtr)>),
     Let(Pat, ExpPtr, ExpPtr),
     Letrec(Pat, ExpPtr, ExpPtr),
 }
@@ -1,4 +1,5 @@
 mod ast;
+mod deps;
 mod exp;
 mod loc;
 mod op_code;
@@ -7,6 +8,7 @@ mod ty;
 mod types;
 
 pub use ast::*;
+pub use deps::*;
 pub use exp::*;
 pub use loc::*;
 pub use op_code::*;
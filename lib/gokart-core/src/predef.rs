@@ -1,4 +1,4 @@
-use crate::{BinOp, Exp, Pat, Type, TypeIdx, UnOp};
+use crate::{Assoc, BinOp, Exp, Fixity, NullOp, Pat, Type, TypeIdx, UnOp};
 use derive_new::new;
 use std::collections::HashMap;
 
@@ -12,13 +12,22 @@ pub struct PredefFunc {
     pub ty: Type,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OprKind {
+    Bin(BinOp),
+    // the right operand is evaluated only when needed
+    And,
+    Or,
+}
+
 #[derive(Debug, new)]
 pub struct PredefOpr {
     pub name: &'static str,
-    pub bin_op: BinOp,
+    pub kind: OprKind,
     pub left_ty: Type,
     pub right_ty: Type,
     pub res_ty: Type,
+    pub fixity: Fixity,
 }
 
 impl Predef {
@@ -38,6 +47,10 @@ impl Predef {
         "Str"
     }
 
+    fn bool() -> &'static str {
+        "Bool"
+    }
+
     fn vi() -> &'static str {
         "VectorInt"
     }
@@ -49,6 +62,7 @@ impl Predef {
             (Predef::double(), ty_cnt.step()),
             (Predef::str(), ty_cnt.step()),
             (Predef::vi(), ty_cnt.step()),
+            (Predef::bool(), ty_cnt.step()),
         ])
     }
 
@@ -62,8 +76,17 @@ impl Predef {
         let double_ty = get_prim(Predef::double());
         let str_ty = get_prim(Predef::str());
         let vi_ty = get_prim(Predef::vi());
+        let bool_ty = get_prim(Predef::bool());
 
         Vec::from_iter([
+            PredefFunc::new(
+                "not",
+                Exp::Abs(
+                    Pat::Var(0),
+                    Exp::Cond(Exp::Var(0).ptr(), false.as_exp().ptr(), true.as_exp().ptr()).ptr(),
+                ),
+                Type::func(bool_ty.clone(), bool_ty),
+            ),
             un_func("print", Print, &str_ty, &unit_ty),
             un_func("read", Read, &unit_ty, &str_ty),
             un_func("i2s", Int2Str, &int_ty, &str_ty),
@@ -102,31 +125,56 @@ impl Predef {
         let int_ty = get_prim(Predef::int());
         let double_ty = get_prim(Predef::double());
         let str_ty = get_prim(Predef::str());
+        let bool_ty = get_prim(Predef::bool());
+
+        let mul = Fixity::new(Assoc::Left, 7);
+        let add = Fixity::new(Assoc::Left, 6);
+        let cat = Fixity::new(Assoc::Right, 5);
+        let cmp = Fixity::new(Assoc::None, 4);
+        let and = Fixity::new(Assoc::Right, 3);
+        let or = Fixity::new(Assoc::Right, 2);
+
+        let int_opr = |name, bin_op, res_ty, fx| opr(name, bin_op, &int_ty, &int_ty, res_ty, fx);
+        let double_opr =
+            |name, bin_op, res_ty, fx| opr(name, bin_op, &double_ty, &double_ty, res_ty, fx);
+        let str_opr = |name, bin_op, res_ty, fx| opr(name, bin_op, &str_ty, &str_ty, res_ty, fx);
+        let bool_opr = |name, kind, fx| {
+            PredefOpr::new(
+                name,
+                kind,
+                bool_ty.clone(),
+                bool_ty.clone(),
+                bool_ty.clone(),
+                fx,
+            )
+        };
 
         Vec::from_iter([
-            opr("+", IntPlus, &int_ty, &int_ty, &int_ty),
-            opr("*", IntMul, &int_ty, &int_ty, &int_ty),
-            opr("-", IntMinus, &int_ty, &int_ty, &int_ty),
-            opr("/", IntDiv, &int_ty, &int_ty, &int_ty),
-            opr("<", IntLt, &int_ty, &int_ty, &int_ty),
-            opr("<=", IntLe, &int_ty, &int_ty, &int_ty),
-            opr("==", IntEq, &int_ty, &int_ty, &int_ty),
-            opr("!=", IntNe, &int_ty, &int_ty, &int_ty),
-            opr(">", IntGt, &int_ty, &int_ty, &int_ty),
-            opr(">=", IntGe, &int_ty, &int_ty, &int_ty),
-            opr("+%", DoublePlus, &double_ty, &double_ty, &double_ty),
-            opr("*%", DoubleMul, &double_ty, &double_ty, &double_ty),
-            opr("-%", DoubleMinus, &double_ty, &double_ty, &double_ty),
-            opr("/%", DoubleDiv, &double_ty, &double_ty, &double_ty),
-            opr("<%", DoubleLt, &double_ty, &double_ty, &double_ty),
-            opr("<=%", DoubleLe, &double_ty, &double_ty, &double_ty),
-            opr("==%", DoubleEq, &double_ty, &double_ty, &double_ty),
-            opr("!=%", DoubleNe, &double_ty, &double_ty, &double_ty),
-            opr(">%", DoubleGt, &double_ty, &double_ty, &double_ty),
-            opr(">=%", DoubleGe, &double_ty, &double_ty, &double_ty),
-            opr("++", StrPlus, &str_ty, &str_ty, &str_ty),
-            opr("=&=", StrEq, &str_ty, &str_ty, &int_ty),
-            opr("!&=", StrNe, &str_ty, &str_ty, &int_ty),
+            int_opr("+", IntPlus, &int_ty, add),
+            int_opr("*", IntMul, &int_ty, mul),
+            int_opr("-", IntMinus, &int_ty, add),
+            int_opr("/", IntDiv, &int_ty, mul),
+            int_opr("<", IntLt, &bool_ty, cmp),
+            int_opr("<=", IntLe, &bool_ty, cmp),
+            int_opr("==", IntEq, &bool_ty, cmp),
+            int_opr("!=", IntNe, &bool_ty, cmp),
+            int_opr(">", IntGt, &bool_ty, cmp),
+            int_opr(">=", IntGe, &bool_ty, cmp),
+            double_opr("+%", DoublePlus, &double_ty, add),
+            double_opr("*%", DoubleMul, &double_ty, mul),
+            double_opr("-%", DoubleMinus, &double_ty, add),
+            double_opr("/%", DoubleDiv, &double_ty, mul),
+            double_opr("<%", DoubleLt, &bool_ty, cmp),
+            double_opr("<=%", DoubleLe, &bool_ty, cmp),
+            double_opr("==%", DoubleEq, &bool_ty, cmp),
+            double_opr("!=%", DoubleNe, &bool_ty, cmp),
+            double_opr(">%", DoubleGt, &bool_ty, cmp),
+            double_opr(">=%", DoubleGe, &bool_ty, cmp),
+            str_opr("++", StrPlus, &str_ty, cat),
+            str_opr("=&=", StrEq, &bool_ty, cmp),
+            str_opr("!&=", StrNe, &bool_ty, cmp),
+            bool_opr("&&", OprKind::And, and),
+            bool_opr("||", OprKind::Or, or),
         ])
     }
 }
@@ -171,20 +219,30 @@ fn opr(
     left_ty: &Type,
     right_ty: &Type,
     res_ty: &Type,
+    fixity: Fixity,
 ) -> PredefOpr {
     PredefOpr::new(
         name,
-        bin_op,
+        OprKind::Bin(bin_op),
         left_ty.clone(),
         right_ty.clone(),
         res_ty.clone(),
+        fixity,
     )
 }
 
-trait AsExp {
+pub trait AsExp {
     fn as_exp(&self) -> Exp;
 }
 
+// booleans are represented by integers at runtime
+impl AsExp for bool {
+    #[inline]
+    fn as_exp(&self) -> Exp {
+        Exp::Sys0(NullOp::IntLit(*self as i64))
+    }
+}
+
 impl AsExp for UnOp {
     #[inline]
     fn as_exp(&self) -> Exp {
@@ -206,6 +264,19 @@ impl AsExp for BinOp {
     }
 }
 
+// both operands are evaluated, since they are already values
+impl AsExp for OprKind {
+    #[inline]
+    fn as_exp(&self) -> Exp {
+        let body = match self {
+            OprKind::Bin(bin_op) => return bin_op.as_exp(),
+            OprKind::And => Exp::Cond(Exp::Var(0).ptr(), Exp::Var(1).ptr(), false.as_exp().ptr()),
+            OprKind::Or => Exp::Cond(Exp::Var(0).ptr(), true.as_exp().ptr(), Exp::Var(1).ptr()),
+        };
+        Exp::Abs(Pat::Var(0), Exp::Abs(Pat::Var(1), body.ptr()).ptr())
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct Counter {
     val: usize,
@@ -6,6 +6,7 @@ pub type FreeIdx = usize;
 #[derive(Debug, Clone)]
 pub enum Type {
     Prim(TypeIdx),
+    App(TypeIdx, Vec<Type>),
     Pair(TypePtr, TypePtr),
     Func(TypePtr, TypePtr),
     Free(FreeIdx),
@@ -26,12 +27,22 @@ impl Type {
     pub fn pair(from: Type, into: Type) -> Type {
         Type::Pair(from.ptr(), into.ptr())
     }
+
+    // nullary type constructors are always represented by `Prim`
+    #[inline]
+    pub fn app(idx: TypeIdx, args: Vec<Type>) -> Type {
+        match args.is_empty() {
+            true => Type::Prim(idx),
+            false => Type::App(idx, args),
+        }
+    }
 }
 
 impl Type {
     pub fn apply(&self, subst: &Subst) -> Type {
         match self {
             Type::Prim(idx) => Type::Prim(*idx),
+            Type::App(idx, args) => Type::App(*idx, args.iter().map(|a| a.apply(subst)).collect()),
             Type::Pair(left, right) => {
                 Type::Pair(left.apply(subst).ptr(), right.apply(subst).ptr())
             }
@@ -0,0 +1,379 @@
+use crate::{ctx::Ctx, decay::Decay};
+use gokart_core::{BinOp, Exp, Lit, Pat, Term, Tpl, Var, VarName};
+
+// one branch of a match, its columns correspond to the occurrences
+#[derive(Debug)]
+pub struct Row<'t, 'a> {
+    // `None` matches everything
+    tpls: Vec<Option<&'t Tpl<'a>>>,
+    binds: Vec<(VarName<'a>, Var)>,
+    guard: Option<&'t Term<'a>>,
+    body: &'t Term<'a>,
+}
+
+impl<'t, 'a> Row<'t, 'a> {
+    #[inline]
+    pub fn new(tpl: &'t Tpl<'a>, guard: Option<&'t Term<'a>>, body: &'t Term<'a>) -> Self {
+        Self {
+            tpls: vec![Some(tpl)],
+            binds: Vec::new(),
+            guard,
+            body,
+        }
+    }
+
+    // binds the variables and strips the irrefutable parts of the patterns
+    fn normalize(&mut self, occs: &[Var]) {
+        for (tpl, occ) in self.tpls.iter_mut().zip(occs) {
+            while let Some(x) = tpl {
+                match x {
+                    Tpl::Var(name) => {
+                        self.binds.push((name.val, *occ));
+                        *tpl = None;
+                    }
+                    Tpl::As(x) => {
+                        self.binds.push((x.name.val, *occ));
+                        *tpl = Some(&x.tpl);
+                    }
+                    Tpl::Ann(x) => *tpl = Some(&x.tpl),
+                    Tpl::Empty(_) | Tpl::Wild(_) => *tpl = None,
+                    Tpl::Pair(_) | Tpl::Con(_) | Tpl::Lit(_) => break,
+                }
+            }
+        }
+    }
+
+    // replaces the column with the given ones at the end of the row
+    fn expand(&self, col: usize, tpls: &[Option<&'t Tpl<'a>>]) -> Self {
+        let mut res = self.tpls.clone();
+        res.remove(col);
+        res.extend_from_slice(tpls);
+
+        Self {
+            tpls: res,
+            binds: self.binds.clone(),
+            guard: self.guard,
+            body: self.body,
+        }
+    }
+}
+
+#[inline]
+fn expand_occs(occs: &[Var], col: usize, vars: &[Var]) -> Vec<Var> {
+    let mut res = occs.to_vec();
+    res.remove(col);
+    res.extend_from_slice(vars);
+    res
+}
+
+// compiles the rows into a decision tree, so every value is inspected at most once
+pub fn compile<'t, 'a>(occs: Vec<Var>, mut rows: Vec<Row<'t, 'a>>, ctx: &mut Ctx<'a>) -> Exp {
+    rows.iter_mut().for_each(|row| row.normalize(&occs));
+
+    let first = match rows.first() {
+        Some(first) => first,
+        // exhaustiveness is checked at the verify stage
+        None => return Exp::Empty,
+    };
+
+    let col = match first.tpls.iter().position(Option::is_some) {
+        Some(col) => col,
+        None => {
+            let prevs = first
+                .binds
+                .iter()
+                .map(|(name, var)| ctx.bind_var(name, *var))
+                .collect::<Vec<_>>();
+            let guard = first.guard.map(|guard| guard.decay(ctx));
+            let res = first.body.decay(ctx);
+            first
+                .binds
+                .iter()
+                .zip(prevs)
+                .rev()
+                .for_each(|((name, _), prev)| ctx.pop_var(name, prev));
+
+            // when the guard fails, the value falls through to the rows below
+            return match guard {
+                Some(guard) => {
+                    let rest = compile(occs, rows.split_off(1), ctx);
+                    Exp::Cond(guard.ptr(), res.ptr(), rest.ptr())
+                }
+                None => res,
+            };
+        }
+    };
+
+    match first.tpls[col] {
+        Some(Tpl::Pair(_)) => {
+            let (left, right) = (ctx.next_var(), ctx.next_var());
+            let rows = rows
+                .iter()
+                .map(|row| match row.tpls[col] {
+                    Some(Tpl::Pair(tpl)) => row.expand(col, &[Some(&tpl.left), Some(&tpl.right)]),
+                    _ => row.expand(col, &[None, None]),
+                })
+                .collect();
+
+            let occ = Exp::Var(occs[col]);
+            let pat = Pat::Pair(Pat::Var(left).ptr(), Pat::Var(right).ptr());
+            let body = compile(expand_occs(&occs, col, &[left, right]), rows, ctx);
+            Exp::Let(pat, occ.ptr(), body.ptr())
+        }
+        Some(Tpl::Con(tpl)) => {
+            let mut tags = Vec::new();
+            for row in rows.iter() {
+                if let Some(Tpl::Con(x)) = row.tpls[col] {
+                    let tag = ctx.tag(&x.name);
+                    if !tags.contains(&tag) {
+                        tags.push(tag);
+                    }
+                }
+            }
+
+            // the constructors not mentioned in the column share one branch
+            let default = (tags.len() < ctx.siblings(ctx.tag(&tpl.name)).len()).then(|| {
+                let rows = rows
+                    .iter()
+                    .filter(|row| row.tpls[col].is_none())
+                    .map(|row| row.expand(col, &[]))
+                    .collect();
+                let body = compile(expand_occs(&occs, col, &[]), rows, ctx);
+                (Pat::Empty, body.ptr())
+            });
+
+            let branches = tags
+                .into_iter()
+                .map(|tag| {
+                    let var = ctx.next_var();
+                    let rows = rows
+                        .iter()
+                        .filter_map(|row| match row.tpls[col] {
+                            Some(Tpl::Con(x)) if ctx.tag(&x.name) == tag => {
+                                Some(row.expand(col, &[Some(&x.body)]))
+                            }
+                            Some(_) => None,
+                            None => Some(row.expand(col, &[None])),
+                        })
+                        .collect();
+
+                    let body = compile(expand_occs(&occs, col, &[var]), rows, ctx);
+                    (tag, Pat::Var(var), body)
+                })
+                .collect();
+
+            Exp::Case(Exp::Var(occs[col]).ptr(), branches, default)
+        }
+        Some(Tpl::Lit(_)) => {
+            let mut lits: Vec<&Lit> = Vec::new();
+            for row in rows.iter() {
+                if let Some(Tpl::Lit(x)) = row.tpls[col] {
+                    if !lits.iter().any(|lit| same_lit(lit, x)) {
+                        lits.push(x);
+                    }
+                }
+            }
+
+            // literals can't cover all the values, so there is always a default
+            let rows_with = |lit: Option<&Lit>| {
+                rows.iter()
+                    .filter(|row| match (row.tpls[col], lit) {
+                        (Some(Tpl::Lit(x)), Some(lit)) => same_lit(x, lit),
+                        (Some(_), _) => false,
+                        (None, _) => true,
+                    })
+                    .map(|row| row.expand(col, &[]))
+                    .collect::<Vec<_>>()
+            };
+            let default = compile(expand_occs(&occs, col, &[]), rows_with(None), ctx);
+
+            lits.into_iter().rev().fold(default, |acc, lit| {
+                let body = compile(expand_occs(&occs, col, &[]), rows_with(Some(lit)), ctx);
+                let op = match lit {
+                    Lit::Str(_) => BinOp::StrEq,
+                    _ => BinOp::IntEq,
+                };
+                let cond = Exp::Sys2(op, Exp::Var(occs[col]).ptr(), lit.decay(ctx).ptr());
+                Exp::Cond(cond.ptr(), body.ptr(), acc.ptr())
+            })
+        }
+        _ => unreachable!("Only pairs, constructors and literals remain after normalization"),
+    }
+}
+
+fn same_lit(left: &Lit, right: &Lit) -> bool {
+    match (left, right) {
+        (Lit::Int(left), Lit::Int(right)) => left.val == right.val,
+        (Lit::Str(left), Lit::Str(right)) => left.val == right.val,
+        _ => false,
+    }
+}
+
+// whether the pattern inspects the value, so it has to be lowered by the match compiler
+pub fn is_refutable(tpl: &Tpl) -> bool {
+    match tpl {
+        Tpl::Empty(_) | Tpl::Var(_) | Tpl::Wild(_) => false,
+        Tpl::Pair(tpl) => is_refutable(&tpl.left) || is_refutable(&tpl.right),
+        Tpl::As(tpl) => is_refutable(&tpl.tpl),
+        Tpl::Con(_) | Tpl::Lit(_) => true,
+        Tpl::Ann(tpl) => is_refutable(&tpl.tpl),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decay;
+    use gokart_core::{DoubleNegs, Exp, NullOp, Pat, Var};
+    use gokart_parse::parse;
+    use std::collections::HashMap;
+
+    // the variables are numbered in the order of appearance
+    #[derive(Default)]
+    struct Names(HashMap<Var, usize>);
+
+    impl Names {
+        fn name(&mut self, var: Var) -> String {
+            let n = self.0.len();
+            format!("v{}", self.0.entry(var).or_insert(n))
+        }
+    }
+
+    fn show_pat(pat: &Pat, names: &mut Names) -> String {
+        match pat {
+            Pat::Empty => "()".to_string(),
+            Pat::Var(var) => names.name(*var),
+            Pat::Pair(a, b) => format!("({}, {})", show_pat(a, names), show_pat(b, names)),
+            Pat::Layer(var, pat) => format!("{}@{}", names.name(*var), show_pat(pat, names)),
+        }
+    }
+
+    fn show(exp: &Exp, names: &mut Names) -> String {
+        match exp {
+            Exp::Empty => "()".to_string(),
+            Exp::Var(var) => names.name(*var),
+            Exp::Sys0(NullOp::IntLit(x)) => x.to_string(),
+            Exp::Sys0(NullOp::DoubleLit(x)) => x.to_string(),
+            Exp::Sys0(NullOp::StrLit(x)) => format!("{x:?}"),
+            Exp::Sys1(op, a) => format!("({op:?} {})", show(a, names)),
+            Exp::Sys2(op, a, b) => format!("({op:?} {} {})", show(a, names), show(b, names)),
+            Exp::Pair(a, b) => format!("({}, {})", show(a, names), show(b, names)),
+            Exp::Con(tag, a) => format!("#{tag} {}", show(a, names)),
+            Exp::App(a, b) => format!("({} {})", show(a, names), show(b, names)),
+            Exp::Abs(pat, a) => format!("\\{} -> {}", show_pat(pat, names), show(a, names)),
+            Exp::Cond(a, b, c) => format!(
+                "if {} then {} else {}",
+                show(a, names),
+                show(b, names),
+                show(c, names)
+            ),
+            Exp::Case(a, branches, default) => {
+                let mut res = format!("case {} of", show(a, names));
+                for (tag, pat, body) in branches {
+                    let (pat, body) = (show_pat(pat, names), show(body, names));
+                    res += &format!(" | #{tag} {pat} -> {body};");
+                }
+                if let Some((pat, body)) = default {
+                    let (pat, body) = (show_pat(pat, names), show(body, names));
+                    res += &format!(" | _ {pat} -> {body};");
+                }
+                format!("({res})")
+            }
+            Exp::Let(pat, a, b) | Exp::Letrec(pat, a, b) => {
+                let kind = if matches!(exp, Exp::Let(..)) {
+                    "let"
+                } else {
+                    "letrec"
+                };
+                let pat = show_pat(pat, names);
+                format!("{kind} {pat} = {} in {}", show(a, names), show(b, names))
+            }
+        }
+    }
+
+    // the program has to be a lambda, its body is shown without the predefined functions
+    fn assert_decay(input: &str, expected: &str) {
+        let ast = parse(input).unwrap_or_else(|e| panic!("Unexpected error: {e:?}"));
+        match decay(&ast, &DoubleNegs::new()) {
+            Exp::Let(_, _, body) => match *body {
+                Exp::Abs(pat, body) => {
+                    let mut names = Names::default();
+                    show_pat(&pat, &mut names);
+                    assert_eq!(expected, show(&body, &mut names), "for {input}");
+                }
+                exp => panic!("Expected lambda, got {exp:?}"),
+            },
+            exp => panic!("Expected predefined functions, got {exp:?}"),
+        }
+    }
+
+    const LIST: &str = "data List a = Nil | Cons (a, List a)\n";
+
+    #[test]
+    fn ok_nested_con_patterns() {
+        let input = format!(
+            "{LIST}\\xs -> case xs of | Nil -> 0; | Cons (x, Nil) -> x; | Cons (x, Cons (y, _)) -> y;"
+        );
+        let expected = "let v1 = v0 in (case v1 of \
+            | #1 v2 -> 0; \
+            | #2 v3 -> let (v4, v5) = v3 in (case v5 of \
+                | #1 v6 -> v4; \
+                | #2 v7 -> let (v8, v9) = v7 in v8;);)";
+        assert_decay(&input, expected);
+    }
+
+    #[test]
+    fn ok_con_default_branch() {
+        let input = format!("{LIST}\\xs -> case xs of | Cons (x, _) -> x; | _ -> 0;");
+        let expected = "let v1 = v0 in (case v1 of \
+            | #2 v2 -> let (v3, v4) = v2 in v3; \
+            | _ () -> 0;)";
+        assert_decay(&input, expected);
+    }
+
+    #[test]
+    fn ok_pair_inside_con() {
+        let input = format!(
+            "{LIST}data Box a = Box a\n\\b -> case b of | Box (Nil, y) -> y; | Box (Cons (x, _), _) -> x;"
+        );
+        let expected = "let v1 = v0 in (case v1 of \
+            | #3 v2 -> let (v3, v4) = v2 in (case v3 of \
+                | #1 v5 -> v4; \
+                | #2 v6 -> let (v7, v8) = v6 in v7;);)";
+        assert_decay(&input, expected);
+    }
+
+    #[test]
+    fn ok_failed_guard_falls_through() {
+        let input = format!(
+            "{LIST}\\xs -> case xs of | Cons (x, _) if x > 0 -> x; | Cons (x, _) if x < 0 -> 1; | _ -> 0;"
+        );
+        // both rows bind `x` to the same field, the catch-all is also the default branch
+        let expected = "let v1 = v0 in (case v1 of \
+            | #2 v2 -> let (v3, v4) = v2 in \
+                if (IntGt v3 0) then v3 else if (IntLt v3 0) then 1 else 0; \
+            | _ () -> 0;)";
+        assert_decay(&input, expected);
+    }
+
+    #[test]
+    fn ok_literal_patterns() {
+        let inputs = [
+            (
+                "\\n -> case n of | 1 -> 10; | 2 -> 20; | _ -> 0;",
+                "let v1 = v0 in if (IntEq v1 1) then 10 else if (IntEq v1 2) then 20 else 0",
+            ),
+            (
+                "\\s -> case s of | \"a\" -> 1; | \"b\" -> 2; | _ -> 0;",
+                "let v1 = v0 in if (StrEq v1 \"a\") then 1 else if (StrEq v1 \"b\") then 2 else 0",
+            ),
+            (
+                "\\n -> case n of | 1 -> 10; | x -> x;",
+                "let v1 = v0 in if (IntEq v1 1) then 10 else v1",
+            ),
+        ];
+
+        for (input, expected) in inputs {
+            assert_decay(input, expected);
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use gokart_core::{BinOp, Counter, Exp, Pat, Predef, Tag, Var, VarName};
+use gokart_core::{Counter, DoubleNegs, Exp, Loc, LocExt, OprKind, Pat, Predef, Tag, Var, VarName};
 use std::collections::HashMap;
 
 #[derive(Debug)]
@@ -7,13 +7,16 @@ pub struct Ctx<'a> {
     tag_cnt: Counter,
     vars: HashMap<VarName<'a>, Var>,
     tags: HashMap<VarName<'a>, Tag>,
+    siblings: HashMap<Tag, Vec<Tag>>,
     funcs: HashMap<Var, Exp>,
-    oprs: HashMap<VarName<'a>, BinOp>,
+    oprs: HashMap<VarName<'a>, OprKind>,
+    defs: Vec<(Pat, Exp, bool)>,
+    double_negs: DoubleNegs,
 }
 
 impl<'a> Ctx<'a> {
     #[inline]
-    pub fn with_predef() -> Self {
+    pub fn with_predef(double_negs: &DoubleNegs) -> Self {
         let tys = Predef::types(&mut Counter::default());
         let mut var_cnt = Counter::default();
         let mut funcs = HashMap::new();
@@ -27,7 +30,7 @@ impl<'a> Ctx<'a> {
 
         let oprs = Predef::oprs(&tys)
             .into_iter()
-            .map(|opr| (opr.name, opr.bin_op))
+            .map(|opr| (opr.name, opr.kind))
             .collect();
 
         Self {
@@ -35,8 +38,11 @@ impl<'a> Ctx<'a> {
             tag_cnt: Counter::default(),
             vars,
             tags: HashMap::new(),
+            siblings: HashMap::new(),
             funcs,
             oprs,
+            defs: Vec::new(),
+            double_negs: double_negs.clone(),
         }
     }
 
@@ -53,6 +59,17 @@ impl<'a> Ctx<'a> {
         self.vars.insert(name, self.var_cnt.step())
     }
 
+    #[inline]
+    pub fn next_var(&mut self) -> Var {
+        self.var_cnt.step()
+    }
+
+    // the name refers to an already existing variable
+    #[inline]
+    pub fn bind_var(&mut self, name: VarName<'a>, var: Var) -> Option<Var> {
+        self.vars.insert(name, var)
+    }
+
     #[inline]
     pub fn pop_var(&mut self, name: VarName<'a>, prev: Option<Var>) {
         match prev {
@@ -80,17 +97,50 @@ impl<'a> Ctx<'a> {
     }
 
     #[inline]
-    pub fn add_tag(&mut self, name: VarName<'a>) -> Option<Tag> {
-        self.tags.insert(name, self.tag_cnt.step() as u64)
+    pub fn add_tag(&mut self, name: VarName<'a>) -> Tag {
+        let tag = self.tag_cnt.step() as u64;
+        self.tags.insert(name, tag);
+        tag
+    }
+
+    // the tags of all the constructors of the same type
+    #[inline]
+    pub fn siblings(&self, tag: Tag) -> Vec<Tag> {
+        self.siblings.get(&tag).unwrap().clone()
+    }
+
+    #[inline]
+    pub fn add_siblings(&mut self, tags: Vec<Tag>) {
+        for tag in tags.iter() {
+            self.siblings.insert(*tag, tags.clone());
+        }
+    }
+
+    // user-defined operations are not predefined, they are ordinary variables
+    #[inline]
+    pub fn opr(&mut self, name: VarName<'a>) -> Option<OprKind> {
+        self.oprs.get(name).copied()
     }
 
     #[inline]
-    pub fn opr(&mut self, name: VarName<'a>) -> BinOp {
-        *self.oprs.get(name).unwrap()
+    pub fn is_double_neg(&self, loc: Loc<'a>) -> bool {
+        self.double_negs.contains(&loc.begin())
+    }
+
+    #[inline]
+    pub fn add_def(&mut self, pat: Pat, exp: Exp, rec: bool) {
+        self.defs.push((pat, exp, rec));
     }
 
     #[inline]
     pub fn wrap(self, body: Exp) -> Exp {
+        let body = self
+            .defs
+            .into_iter()
+            .rfold(body, |acc, (pat, exp, rec)| match rec {
+                true => Exp::Letrec(pat, exp.ptr(), acc.ptr()),
+                false => Exp::Let(pat, exp.ptr(), acc.ptr()),
+            });
         let (pat, exp) =
             self.funcs
                 .into_iter()
@@ -1,10 +1,14 @@
-use crate::ctx::Ctx;
+use crate::{
+    case::{compile, is_refutable, Row},
+    ctx::Ctx,
+};
 use gokart_core::{
-    Abs, App, AsTpl, Ast, Case, ConTerm, Cond, Def, EmptyTerm, EmptyTpl, Exp, Let, Letrec, Lit,
-    Name, NullOp, Opr, PairTerm, PairTpl, Pat, Term, Tpl, TypeDef, VarName,
+    groups, Abs, App, AsExp, AsTpl, Ast, BinOp, Case, ConTerm, Cond, Def, DoubleNegs, EmptyTerm,
+    EmptyTpl, Exp, ExpPtr, Int, Let, Letrec, Lit, Name, Neg, NullOp, Opr, OprKind, PairTerm,
+    PairTpl, Pat, Section, Seq, Term, Tpl, TypeDef, VarName, WildTpl,
 };
 
-trait Decay<'a> {
+pub trait Decay<'a> {
     fn decay(&self, ctx: &mut Ctx<'a>) -> Exp;
 }
 
@@ -32,7 +36,9 @@ impl<'a> Decay<'a> for Lit<'a> {
         let null_op = match self {
             Lit::Int(lit) => NullOp::IntLit(lit.val),
             Lit::Double(lit) => NullOp::DoubleLit(lit.val),
-            Lit::Str(lit) => NullOp::StrLit(String::from(lit.val)),
+            Lit::Str(lit) => NullOp::StrLit(lit.val.clone()),
+            // booleans are represented by integers at runtime
+            Lit::Bool(lit) => NullOp::IntLit(lit.val as Int),
         };
         Exp::Sys0(null_op)
     }
@@ -45,14 +51,61 @@ impl<'a> Decay<'a> for ConTerm<'a> {
     }
 }
 
+fn decay_opr<'a>(name: &Name<'a>, left: ExpPtr, right: ExpPtr, ctx: &mut Ctx<'a>) -> Exp {
+    let bool_lit = |val: bool| Exp::Sys0(NullOp::IntLit(val as Int)).ptr();
+
+    match ctx.opr(name) {
+        Some(OprKind::Bin(bin_op)) => Exp::Sys2(bin_op, left, right),
+        Some(OprKind::And) => Exp::Cond(left, right, bool_lit(false)),
+        Some(OprKind::Or) => Exp::Cond(left, bool_lit(true), right),
+        None => {
+            let head = Exp::App(name.decay(ctx).ptr(), left);
+            Exp::App(head.ptr(), right)
+        }
+    }
+}
+
 impl<'a> Decay<'a> for Opr<'a> {
     fn decay(&self, ctx: &mut Ctx<'a>) -> Exp {
-        let bin_op = ctx.opr(&self.name);
-        Exp::Sys2(
-            bin_op,
-            self.left.decay(ctx).ptr(),
-            self.right.decay(ctx).ptr(),
-        )
+        let left = self.left.decay(ctx).ptr();
+        let right = self.right.decay(ctx).ptr();
+        decay_opr(&self.name, left, right, ctx)
+    }
+}
+
+impl<'a> Decay<'a> for Section<'a> {
+    fn decay(&self, ctx: &mut Ctx<'a>) -> Exp {
+        // the curried form of the operation
+        let func = match ctx.opr(&self.name) {
+            Some(kind) => kind.as_exp(),
+            None => self.name.decay(ctx),
+        };
+
+        match (&self.left, &self.right) {
+            (None, None) => func,
+            (Some(left), None) => Exp::App(func.ptr(), left.decay(ctx).ptr()),
+            (left, Some(right)) => {
+                // the operand is evaluated once, when the section is created
+                let (var, arg) = (ctx.next_var(), ctx.next_var());
+                let left = match left {
+                    Some(left) => left.decay(ctx),
+                    None => Exp::Var(arg),
+                };
+                let body = decay_opr(&self.name, left.ptr(), Exp::Var(var).ptr(), ctx);
+                let exp = Exp::Abs(Pat::Var(arg), body.ptr());
+                Exp::Let(Pat::Var(var), right.decay(ctx).ptr(), exp.ptr())
+            }
+        }
+    }
+}
+
+impl<'a> Decay<'a> for Neg<'a> {
+    fn decay(&self, ctx: &mut Ctx<'a>) -> Exp {
+        let (op, lit) = match ctx.is_double_neg(self.loc) {
+            true => (BinOp::DoubleMul, NullOp::DoubleLit(-1.0)),
+            false => (BinOp::IntMul, NullOp::IntLit(-1)),
+        };
+        Exp::Sys2(op, Exp::Sys0(lit).ptr(), self.term.decay(ctx).ptr())
     }
 }
 
@@ -74,33 +127,35 @@ impl<'a> Decay<'a> for Cond<'a> {
 
 impl<'a> Decay<'a> for Abs<'a> {
     fn decay(&self, ctx: &mut Ctx<'a>) -> Exp {
-        let prev = ctx.push_var(&self.arg);
-        let res = Exp::Abs(self.arg.as_pat(ctx), self.body.decay(ctx).ptr());
-        ctx.pop_var(&self.arg, prev);
+        if is_refutable(&self.arg) {
+            let var = ctx.next_var();
+            let body = compile(vec![var], vec![Row::new(&self.arg, None, &self.body)], ctx);
+            return Exp::Abs(Pat::Var(var), body.ptr());
+        }
+
+        let names = self.arg.get_names();
+        let prevs = ctx.push_vars(&names);
+
+        let pat = self.arg.as_pat(ctx);
+        let res = Exp::Abs(pat, self.body.decay(ctx).ptr());
+
+        ctx.pop_vars(&names, prevs);
         res
     }
 }
 
 impl<'a> Decay<'a> for Case<'a> {
     fn decay(&self, ctx: &mut Ctx<'a>) -> Exp {
-        let body = self.cond.decay(ctx);
-        let branches = self
+        let exp = self.cond.decay(ctx);
+        let var = ctx.next_var();
+        let rows = self
             .branches
             .iter()
-            .map(|branch| {
-                let names = branch.tpl.get_names();
-                let prevs = ctx.push_vars(&names);
-
-                let tag = ctx.tag(&branch.con);
-                let pat = branch.tpl.as_pat(ctx);
-                let exp = branch.body.decay(ctx);
-
-                ctx.pop_vars(&names, prevs);
-                (tag, pat, exp)
-            })
-            .collect::<Vec<_>>();
+            .map(|branch| Row::new(&branch.tpl, branch.guard.as_ref(), &branch.body))
+            .collect();
 
-        Exp::Case(body.ptr(), branches)
+        let body = compile(vec![var], rows, ctx);
+        Exp::Let(Pat::Var(var), exp.ptr(), body.ptr())
     }
 }
 
@@ -108,6 +163,12 @@ impl<'a> Decay<'a> for Let<'a> {
     fn decay(&self, ctx: &mut Ctx<'a>) -> Exp {
         let exp = self.term.decay(ctx);
 
+        if is_refutable(&self.tpl) {
+            let var = ctx.next_var();
+            let body = compile(vec![var], vec![Row::new(&self.tpl, None, &self.body)], ctx);
+            return Exp::Let(Pat::Var(var), exp.ptr(), body.ptr());
+        }
+
         let names = self.tpl.get_names();
         let prevs = ctx.push_vars(&names);
 
@@ -120,6 +181,13 @@ impl<'a> Decay<'a> for Let<'a> {
     }
 }
 
+impl<'a> Decay<'a> for Seq<'a> {
+    fn decay(&self, ctx: &mut Ctx<'a>) -> Exp {
+        let first = self.first.decay(ctx);
+        Exp::Let(Pat::Empty, first.ptr(), self.second.decay(ctx).ptr())
+    }
+}
+
 impl<'a> Decay<'a> for Letrec<'a> {
     fn decay(&self, ctx: &mut Ctx<'a>) -> Exp {
         let names = self.tpl.get_names();
@@ -147,9 +215,13 @@ impl<'a> Decay<'a> for Term<'a> {
             Term::App(term) => term.decay(ctx),
             Term::Cond(term) => term.decay(ctx),
             Term::Abs(term) => term.decay(ctx),
+            Term::Ann(term) => term.term.decay(ctx),
             Term::Case(term) => term.decay(ctx),
             Term::Let(term) => term.decay(ctx),
             Term::Letrec(term) => term.decay(ctx),
+            Term::Seq(term) => term.decay(ctx),
+            Term::Section(term) => term.decay(ctx),
+            Term::Neg(term) => term.decay(ctx),
         }
     }
 }
@@ -164,6 +236,12 @@ impl<'a> AsPat<'a> for EmptyTpl<'a> {
     }
 }
 
+impl<'a> AsPat<'a> for WildTpl<'a> {
+    fn as_pat(&self, _ctx: &mut Ctx<'a>) -> Pat {
+        Pat::Empty
+    }
+}
+
 impl<'a> AsPat<'a> for Name<'a> {
     fn as_pat(&self, ctx: &mut Ctx<'a>) -> Pat {
         let idx = ctx.var(self);
@@ -189,8 +267,13 @@ impl<'a> AsPat<'a> for Tpl<'a> {
         match self {
             Tpl::Empty(tpl) => tpl.as_pat(ctx),
             Tpl::Var(tpl) => tpl.as_pat(ctx),
+            Tpl::Wild(tpl) => tpl.as_pat(ctx),
             Tpl::Pair(tpl) => tpl.as_pat(ctx),
             Tpl::As(tpl) => tpl.as_pat(ctx),
+            Tpl::Ann(tpl) => tpl.tpl.as_pat(ctx),
+            Tpl::Con(_) | Tpl::Lit(_) => {
+                unreachable!("Refutable patterns are lowered by the match compiler")
+            }
         }
     }
 }
@@ -203,7 +286,7 @@ impl<'a> GetNames<'a> for Tpl<'a> {
     fn get_names(&self) -> Vec<VarName<'a>> {
         fn go<'b>(tpl: &Tpl<'b>, names: &mut Vec<VarName<'b>>) {
             match tpl {
-                Tpl::Empty(_) => (),
+                Tpl::Empty(_) | Tpl::Wild(_) | Tpl::Lit(_) => (),
                 Tpl::Var(name) => names.push(name.val),
                 Tpl::Pair(tpl) => {
                     go(&tpl.left, names);
@@ -213,6 +296,8 @@ impl<'a> GetNames<'a> for Tpl<'a> {
                     names.push(tpl.name.val);
                     go(&tpl.tpl, names);
                 }
+                Tpl::Ann(tpl) => go(&tpl.tpl, names),
+                Tpl::Con(tpl) => go(&tpl.body, names),
             }
         }
 
@@ -228,25 +313,47 @@ trait Apply<'a> {
 
 impl<'a> Apply<'a> for TypeDef<'a> {
     fn apply(&self, ctx: &mut Ctx<'a>) {
-        for con in self.cons.iter() {
-            ctx.add_tag(&con.name);
-        }
+        let tags = self.cons.iter().map(|con| ctx.add_tag(&con.name)).collect();
+        ctx.add_siblings(tags);
     }
 }
 
-impl<'a> Apply<'a> for Def<'a> {
-    fn apply(&self, ctx: &mut Ctx<'a>) {
-        match self {
-            Def::TypeDef(type_def) => type_def.apply(ctx),
+pub fn decay(ast: &Ast, double_negs: &DoubleNegs) -> Exp {
+    let mut ctx = Ctx::with_predef(double_negs);
+
+    for def in ast.defs.iter() {
+        if let Def::TypeDef(type_def) = def {
+            type_def.apply(&mut ctx);
         }
     }
-}
 
-pub fn decay(ast: &Ast) -> Exp {
-    let mut ctx = Ctx::with_predef();
-
-    for def in ast.defs.iter() {
-        def.apply(&mut ctx);
+    // stay in scope for the whole program
+    let bindings = ast.bindings();
+    let vars = bindings
+        .iter()
+        .map(|binding| {
+            ctx.push_var(binding.name);
+            ctx.var(binding.name)
+        })
+        .collect::<Vec<_>>();
+
+    for group in groups(&bindings) {
+        let (pats, exps): (Vec<_>, Vec<_>) = group
+            .idxs
+            .iter()
+            .map(|idx| (Pat::Var(vars[*idx]), bindings[*idx].term.decay(&mut ctx)))
+            .unzip();
+
+        // because every group has at least one definition
+        let pat = pats
+            .into_iter()
+            .reduce(|a, b| Pat::Pair(a.ptr(), b.ptr()))
+            .unwrap();
+        let exp = exps
+            .into_iter()
+            .reduce(|a, b| Exp::Pair(a.ptr(), b.ptr()))
+            .unwrap();
+        ctx.add_def(pat, exp, group.rec);
     }
 
     let res = ast.body.decay(&mut ctx);
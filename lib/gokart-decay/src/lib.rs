@@ -1,3 +1,4 @@
+mod case;
 mod ctx;
 mod decay;
 
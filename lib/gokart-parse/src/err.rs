@@ -26,9 +26,29 @@ pub enum ParseErr {
     #[diagnostic()]
     BadDoubleLiteral(#[label("here")] Span, ParseFloatError),
 
+    #[error("Bad escape sequence: {1}")]
+    #[diagnostic()]
+    BadEscape(#[label("here")] Span, String),
+
     #[error("Unexpected token: expected {}, found {}", ._1.as_ref(), ._2.as_ref())]
     #[diagnostic()]
     UnexpectedToken(#[label("here")] Span, TokenKind, TokenKind),
+
+    #[error("Bad operator precedence: expected a number from 0 to 9")]
+    #[diagnostic()]
+    BadPrecedence(#[label("here")] Span),
+
+    #[error("Operators {1} and {2} can't be chained without parentheses")]
+    #[diagnostic()]
+    AmbiguousOpr(#[label("here")] Span, String, String),
+
+    #[error("Unterminated block comment")]
+    #[diagnostic(help("`{{-` always opens a comment, add a space after `{{` to start with `-`"))]
+    UnterminatedComment(#[label("opened here")] Span),
+
+    #[error("Unterminated string literal")]
+    #[diagnostic()]
+    UnterminatedString(#[label("opened here")] Span),
 }
 
 impl ParseErr {
@@ -51,7 +71,12 @@ impl ParseErr {
             NomError(span, _) => span,
             BadIntLiteral(span, _) => span,
             BadDoubleLiteral(span, _) => span,
+            BadEscape(span, _) => span,
             UnexpectedToken(span, _, _) => span,
+            BadPrecedence(span) => span,
+            AmbiguousOpr(span, _, _) => span,
+            UnterminatedComment(span) => span,
+            UnterminatedString(span) => span,
         }
     }
     #[inline]
@@ -75,6 +100,15 @@ impl<'a> ParseError<Loc<'a>> for ParseErr {
     fn from_char(i: Loc<'a>, c: char) -> Self {
         ParseErr::UnexpectedChar(i.into_span(), c)
     }
+
+    // the alternative that got further is the one that was meant
+    #[inline]
+    fn or(self, other: Self) -> Self {
+        match self.begin() > other.begin() {
+            true => self,
+            false => other,
+        }
+    }
 }
 
 pub type ParseRes<'a, T> = IResult<Loc<'a>, T, ParseErr>;
@@ -0,0 +1,30 @@
+use gokart_core::{Assoc, Ast, Counter, Def, Fixity, Predef};
+use std::collections::HashMap;
+
+const DEFAULT: Fixity = Fixity {
+    assoc: Assoc::Left,
+    prec: 9,
+};
+
+// operators missing from the table get the default fixity
+pub type Fixities = HashMap<String, Fixity>;
+
+#[inline]
+pub fn fixity(fxs: &Fixities, name: &str) -> Fixity {
+    fxs.get(name).copied().unwrap_or(DEFAULT)
+}
+
+// the predefined operators along with the ones declared in the program
+pub fn declared(ast: &Ast) -> Fixities {
+    let tys = Predef::types(&mut Counter::default());
+    let predef = Predef::oprs(&tys)
+        .into_iter()
+        .map(|opr| (opr.name.to_string(), opr.fixity));
+
+    let decls = ast.defs.iter().filter_map(|def| match def {
+        Def::OprDef(def) => Some((def.name.val.to_string(), def.fixity)),
+        _ => None,
+    });
+
+    predef.chain(decls).collect()
+}
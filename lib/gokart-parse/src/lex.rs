@@ -1,43 +1,120 @@
 use crate::{
-    err::ParseRes,
+    err::{ParseErr, ParseRes},
     token::{Token, TokenKind},
 };
-use gokart_core::Loc;
+use gokart_core::{Loc, LocExt};
 use nom::{
     branch::alt,
-    bytes::complete::{is_a, tag, take_until, take_while},
-    character::complete::{char as chr, digit1, multispace0, satisfy},
-    combinator::{map, opt, recognize},
+    bytes::complete::{escaped, is_a, is_not, tag, tag_no_case, take_while},
+    character::complete::{anychar, char as chr, digit1, multispace1, one_of, satisfy},
+    combinator::{map, not, opt, peek, recognize, value},
+    multi::many0,
     sequence::{pair, tuple},
+    InputTake, Offset,
 };
+use unicode_ident::{is_xid_continue, is_xid_start};
+
+const OPR_CHARS: &str = "!#$%&*+-./<=>?@\\^|~:";
+
+fn line_comment(i: Loc) -> ParseRes<()> {
+    // a run of dashes followed by another symbol is an operator, e.g. `-->`
+    let head = tuple((
+        tag("--"),
+        take_while(|c| c == '-'),
+        peek(not(satisfy(|c| OPR_CHARS.contains(c)))),
+    ));
+
+    value((), pair(head, take_while(|c| c != '\n')))(i)
+}
+
+fn block_comment(i: Loc) -> ParseRes<()> {
+    let (mut rem, open) = tag("{-")(i)?;
+    let mut depth = 1;
+
+    while depth > 0 {
+        let step = match rem.fragment() {
+            s if s.starts_with("-}") => {
+                depth -= 1;
+                2
+            }
+            s if s.starts_with("{-") => {
+                depth += 1;
+                2
+            }
+            s => match s.chars().next() {
+                Some(c) => c.len_utf8(),
+                None => return ParseErr::UnterminatedComment(open.into_span()).failure(),
+            },
+        };
+        rem = rem.take_split(step).0;
+    }
+
+    Ok((rem, ()))
+}
+
+pub fn trivia(i: Loc) -> ParseRes<()> {
+    let res = many0(alt((value((), multispace1), line_comment, block_comment)));
+
+    value((), res)(i)
+}
 
 fn single<'a>(val: &'static str, kind: TokenKind) -> impl Fn(Loc<'a>) -> ParseRes<Token<'a>> {
     move |i: Loc| map(tag(val), |s| kind.at(s))(i)
 }
 
 fn str_lit(i: Loc) -> ParseRes<Token> {
-    let q = "\"";
-    let res = recognize(tuple((tag(q), take_until(q), tag(q))));
+    // escapes are only skipped here, they are decoded by the parser
+    let body = escaped(is_not("\\\""), '\\', anychar);
+    let (rem, (open, _)) = pair(tag("\""), opt(body))(i)?;
 
-    map(res, |s| TokenKind::Str.at(s))(i)
+    match tag::<_, _, ParseErr>("\"")(rem) {
+        Ok((rem, _)) => Ok((rem, TokenKind::Str.at(i.take(i.offset(&rem))))),
+        Err(_) => ParseErr::UnterminatedString(open.into_span()).failure(),
+    }
+}
+
+fn digits(i: Loc) -> ParseRes<Loc> {
+    recognize(pair(
+        digit1,
+        take_while(|c: char| c.is_ascii_digit() || c == '_'),
+    ))(i)
 }
 
+// the wrong digits for the radix are checked by the parser,
+// so `0b102` is reported as a whole
 fn int_lit(i: Loc) -> ParseRes<Token> {
-    let res = recognize(tuple((opt(chr('-')), digit1)));
+    let prefix = alt((tag_no_case("0x"), tag_no_case("0o"), tag_no_case("0b")));
+    let res = alt((
+        recognize(pair(
+            prefix,
+            take_while(|c: char| c.is_ascii_hexdigit() || c == '_'),
+        )),
+        digits,
+    ));
 
     map(res, |s| TokenKind::Int.at(s))(i)
 }
 
+fn exponent(i: Loc) -> ParseRes<Loc> {
+    recognize(tuple((one_of("eE"), opt(one_of("+-")), digits)))(i)
+}
+
 fn double_lit(i: Loc) -> ParseRes<Token> {
-    let res = recognize(tuple((opt(chr('-')), digit1, chr('.'), digit1)));
+    // `1.` is a double only when it can't be read as `1` followed by a name or an operator
+    let next = |c: char| c.is_ascii_alphanumeric() || c == '_' || OPR_CHARS.contains(c);
+    let res = alt((
+        recognize(tuple((digits, chr('.'), digits, opt(exponent)))),
+        recognize(tuple((digits, opt(chr('.')), exponent))),
+        recognize(tuple((digits, chr('.'), peek(not(satisfy(next)))))),
+    ));
 
     map(res, |s| TokenKind::Double.at(s))(i)
 }
 
 fn ident(i: Loc) -> ParseRes<Token> {
     let res = recognize(pair(
-        satisfy(|c: char| c.is_ascii_alphabetic() || c == '_'),
-        take_while(|c: char| c.is_ascii_alphanumeric() || c == '_'),
+        satisfy(|c: char| is_xid_start(c) || c == '_'),
+        take_while(is_xid_continue),
     ));
 
     map(res, |s: Loc| {
@@ -54,7 +131,12 @@ fn ident(i: Loc) -> ParseRes<Token> {
             "infixl" => TokenKind::Infixl,
             "infixr" => TokenKind::Infixr,
             "as" => TokenKind::As,
-            f if f.starts_with(|c: char| c.is_ascii_uppercase()) => TokenKind::Udent,
+            "do" => TokenKind::Do,
+            "where" => TokenKind::Where,
+            "_" => TokenKind::Wild,
+            "True" => TokenKind::True,
+            "False" => TokenKind::False,
+            f if f.starts_with(char::is_uppercase) => TokenKind::Udent,
             _ => TokenKind::Ident,
         };
 
@@ -63,7 +145,7 @@ fn ident(i: Loc) -> ParseRes<Token> {
 }
 
 fn opr(i: Loc) -> ParseRes<Token> {
-    let res = is_a("!#$%&*+-./<=>?@\\^|~:");
+    let res = is_a(OPR_CHARS);
 
     map(res, |s: Loc| {
         let kind = match *s.fragment() {
@@ -71,6 +153,7 @@ fn opr(i: Loc) -> ParseRes<Token> {
             "\\" => TokenKind::Backslash,
             "|" => TokenKind::Pipe,
             "->" => TokenKind::Arrow,
+            ":" => TokenKind::Colon,
             _ => TokenKind::Opr,
         };
 
@@ -78,8 +161,14 @@ fn opr(i: Loc) -> ParseRes<Token> {
     })(i)
 }
 
+// no token starts with the character, the span covers all of its bytes
+fn unknown(i: Loc) -> ParseRes<Token> {
+    let (_, c) = anychar(i)?;
+    ParseErr::UnexpectedChar(i.take(c.len_utf8()).into_span(), c).failure()
+}
+
 pub fn token(i: Loc) -> ParseRes<Token> {
-    let (i, _) = multispace0(i)?;
+    let (i, _) = trivia(i)?;
 
     alt((
         single("{", TokenKind::LBrace),
@@ -95,6 +184,7 @@ pub fn token(i: Loc) -> ParseRes<Token> {
         int_lit,
         ident,
         opr,
+        unknown,
     ))(i)
 }
 
@@ -102,13 +192,13 @@ pub fn token(i: Loc) -> ParseRes<Token> {
 mod tests {
     use super::*;
     use gokart_core::LocExt;
-    use nom::{combinator::eof, multi::many0, sequence::tuple};
+    use nom::combinator::eof;
     use TokenKind::*;
 
     type Tokens = Vec<(TokenKind, usize, usize)>;
 
     fn lex_tokens(i: Loc) -> ParseRes<Tokens> {
-        let res = tuple((many0(token), multispace0, eof));
+        let res = tuple((many0(token), trivia, eof));
 
         map(res, |(tokens, _, _)| {
             tokens
@@ -127,13 +217,13 @@ mod tests {
         }
     }
 
-    fn assert_lex_error(input: &str, begin: usize) {
+    fn assert_lex_failure(input: &str, begin: usize) {
         let res = lex_tokens(Loc::new(input));
 
         match res {
             Ok(res) => panic!("This test should fail, but got {res:?}"),
             Err(e) => match e {
-                nom::Err::Error(e) => assert_eq!(begin, e.begin()),
+                nom::Err::Failure(e) => assert_eq!(begin, e.begin()),
                 _ => panic!("Unexpected error: {e:?}"),
             },
         }
@@ -151,7 +241,12 @@ mod tests {
 
     #[test]
     fn ok_negative_int_literal() {
-        assert_tokens("-123", vec![(Int, 0, 4)]);
+        assert_tokens("-123", vec![(Opr, 0, 1), (Int, 1, 4)]);
+    }
+
+    #[test]
+    fn ok_minus_without_spaces() {
+        assert_tokens("n-1", vec![(Ident, 0, 1), (Opr, 1, 2), (Int, 2, 3)]);
     }
 
     #[test]
@@ -159,9 +254,61 @@ mod tests {
         assert_tokens("12.33", vec![(Double, 0, 5)]);
     }
 
+    #[test]
+    fn ok_radix_int_literals() {
+        assert_tokens(
+            "0xFF 0o17 0b1010 0b102 12ab",
+            vec![
+                (Int, 0, 4),
+                (Int, 5, 9),
+                (Int, 10, 16),
+                (Int, 17, 22),
+                (Int, 23, 25),
+                (Ident, 25, 27),
+            ],
+        );
+    }
+
+    #[test]
+    fn ok_digit_separators() {
+        assert_tokens(
+            "1_000 0xFF_FF 1_0.5_0",
+            vec![(Int, 0, 5), (Int, 6, 13), (Double, 14, 21)],
+        );
+    }
+
+    #[test]
+    fn ok_exponent_double_literals() {
+        assert_tokens(
+            "1e-9 6.02E23 2.e+3 1e",
+            vec![
+                (Double, 0, 4),
+                (Double, 5, 12),
+                (Double, 13, 18),
+                (Int, 19, 20),
+                (Ident, 20, 21),
+            ],
+        );
+    }
+
+    #[test]
+    fn ok_trailing_dot_double_literals() {
+        assert_tokens(
+            "1. (2.)",
+            vec![
+                (Double, 0, 2),
+                (LParen, 3, 4),
+                (Double, 4, 6),
+                (RParen, 6, 7),
+            ],
+        );
+        assert_tokens("1.x", vec![(Int, 0, 1), (Opr, 1, 2), (Ident, 2, 3)]);
+        assert_tokens("1.+2", vec![(Int, 0, 1), (Opr, 1, 3), (Int, 3, 4)]);
+    }
+
     #[test]
     fn ok_negative_double_literal() {
-        assert_tokens("-1.3", vec![(Double, 0, 4)]);
+        assert_tokens("-1.3", vec![(Opr, 0, 1), (Double, 1, 4)]);
     }
 
     #[test]
@@ -177,6 +324,14 @@ mod tests {
         );
     }
 
+    #[test]
+    fn ok_string_literal_with_escapes() {
+        assert_tokens(
+            r#""say \"hi\"\n" "\\" """#,
+            vec![(Str, 0, 14), (Str, 15, 19), (Str, 20, 22)],
+        );
+    }
+
     #[test]
     fn ok_identifiers() {
         assert_tokens(
@@ -194,6 +349,28 @@ mod tests {
         );
     }
 
+    #[test]
+    fn ok_wildcards() {
+        assert_tokens(
+            "_ _x x_ __",
+            vec![(Wild, 0, 1), (Ident, 2, 4), (Ident, 5, 7), (Ident, 8, 10)],
+        );
+    }
+
+    #[test]
+    fn ok_bool_literals() {
+        assert_tokens(
+            "True && Falsey || False",
+            vec![
+                (True, 0, 4),
+                (Opr, 5, 7),
+                (Udent, 8, 14),
+                (Opr, 15, 17),
+                (False, 18, 23),
+            ],
+        );
+    }
+
     #[test]
     fn ok_operators() {
         assert_tokens(
@@ -221,20 +398,90 @@ mod tests {
                 (RBracket, 7, 8),
                 (LBracket, 8, 9),
                 (LParen, 10, 11),
-                (Int, 11, 14),
+                (Opr, 11, 12),
+                (Int, 12, 14),
                 (RParen, 14, 15),
                 (RParen, 15, 16),
             ],
         );
     }
 
+    #[test]
+    fn ok_line_comments() {
+        assert_tokens(
+            "x -- the comment\n-- another one\ny --",
+            vec![(Ident, 0, 1), (Ident, 32, 33)],
+        );
+    }
+
+    #[test]
+    fn ok_dashes_operator() {
+        assert_tokens("a --> b", vec![(Ident, 0, 1), (Opr, 2, 5), (Ident, 6, 7)]);
+    }
+
+    #[test]
+    fn ok_block_comments() {
+        assert_tokens(
+            "x {- the\ncomment -} y{--}z",
+            vec![(Ident, 0, 1), (Ident, 20, 21), (Ident, 25, 26)],
+        );
+    }
+
+    #[test]
+    fn ok_nested_block_comments() {
+        assert_tokens(
+            "x {- outer {- inner -} -- still outer\n -} y",
+            vec![(Ident, 0, 1), (Ident, 42, 43)],
+        );
+    }
+
+    #[test]
+    fn err_unterminated_block_comment() {
+        assert_lex_failure("x {- outer {- inner -} y", 2);
+    }
+
+    fn assert_unterminated_string(input: &str, begin: usize) {
+        match lex_tokens(Loc::new(input)) {
+            Err(nom::Err::Failure(ParseErr::UnterminatedString(span))) => {
+                assert_eq!((begin, 1), (span.offset(), span.len()))
+            }
+            res => panic!("Expected unterminated string, got {res:?}"),
+        }
+    }
+
     #[test]
     fn err_unclosed_quote() {
-        assert_lex_error(r#"letrec x = "some string"#, 11);
+        assert_unterminated_string(r#"letrec x = "some string"#, 11);
+    }
+
+    #[test]
+    fn err_escaped_closing_quote() {
+        assert_unterminated_string(r#"x "unclosed \""#, 2);
+        assert_unterminated_string(r#""a" "b\"#, 4);
+    }
+
+    #[test]
+    fn ok_unicode_idents() {
+        assert_tokens(
+            "привет Мир _х2 Ωmega",
+            vec![
+                (Ident, 0, 12),
+                (Udent, 13, 19),
+                (Ident, 20, 24),
+                (Udent, 25, 31),
+            ],
+        );
     }
 
     #[test]
     fn err_unexpected_symbol() {
-        assert_lex_error("-> привет мир", 3);
+        assert_lex_failure("-> € мир", 3);
+
+        match lex_tokens(Loc::new("-> € мир")) {
+            Err(nom::Err::Failure(ParseErr::UnexpectedChar(span, c))) => {
+                assert_eq!(('€', 3, 3), (c, span.offset(), span.len()))
+            }
+            res => panic!("Expected unexpected char, got {res:?}"),
+        }
     }
 }
@@ -1,4 +1,5 @@
 mod err;
+mod fixity;
 mod lex;
 mod parse;
 mod token;
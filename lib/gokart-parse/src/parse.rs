@@ -1,30 +1,30 @@
 use crate::{
     err::{ParseErr, ParseRes},
-    lex::token,
+    fixity::{declared, fixity, Fixities},
+    lex::{token, trivia},
     token::{Token, TokenKind},
 };
 use gokart_core::{
-    Abs, App, AsTpl, Ast, Branch, Case, Con, ConTerm, Cond, Def, DoubleLit, EmptyTerm, EmptyTpl,
-    IntLit, Let, Letrec, Lit, Loc, LocExt, Name, Opr, PairTerm, PairTpl, StrLit, Term, Tpl,
-    TypeDef,
+    tpl_groups, Abs, AnnTerm, AnnTpl, App, AsTpl, Assoc, Ast, BoolLit, Branch, Case, Con, ConTerm,
+    ConTpl, ConTypeExpr, Cond, Def, DoubleLit, EmptyTerm, EmptyTpl, EmptyTypeExpr, Fixity,
+    FuncTypeExpr, IntLit, Let, Letrec, Lit, Loc, LocExt, Name, Neg, Opr, OprDef, PairTerm, PairTpl,
+    PairTypeExpr, Section, Seq, StrLit, Term, Tpl, TypeDef, TypeExpr, ValDef, WildTpl,
 };
+use miette::{SourceOffset, SourceSpan as Span};
 use nom::{
     branch::alt,
-    character::complete::multispace0,
-    combinator::{eof, map},
-    error::ParseError,
-    multi::{many0, many1, separated_list0},
-    sequence::tuple,
-    IResult, InputTake, Offset, Parser,
+    combinator::{cut, eof, map, opt, verify},
+    multi::{many0, many1, separated_list0, separated_list1},
+    sequence::{preceded, tuple},
+    InputTake, Offset, Parser,
 };
 
-fn with_loc<'a, O, E, P>(mut p: P) -> impl FnMut(Loc<'a>) -> IResult<Loc<'a>, (Loc<'a>, O), E>
+fn with_loc<'a, O, P>(mut p: P) -> impl FnMut(Loc<'a>) -> ParseRes<'a, (Loc<'a>, O)>
 where
-    P: Parser<Loc<'a>, O, E>,
-    E: ParseError<Loc<'a>>,
+    P: Parser<Loc<'a>, O, ParseErr>,
 {
     move |i: Loc<'a>| {
-        let (i, _) = multispace0(i)?;
+        let (i, _) = trivia(i)?;
         let (rem, res) = p.parse(i)?;
         let loc = i.take(i.offset(&rem));
         Ok((rem, (loc, res)))
@@ -65,10 +65,24 @@ fn opr_name(i: Loc) -> ParseRes<Name> {
     name(TokenKind::Opr)(i)
 }
 
-fn seq_term(i: Loc) -> ParseRes<Term> {
+fn ann(i: Loc) -> ParseRes<TypeExpr> {
+    let (rem, (_, ty)) = tuple((expect(TokenKind::Colon), type_expr))(i)?;
+    Ok((rem, ty))
+}
+
+fn ann_term<'a>(i: Loc<'a>, fxs: &Fixities) -> ParseRes<'a, Term<'a>> {
+    let res = tuple((|i| term(i, fxs), opt(ann)));
+
+    map(with_loc(res), |(loc, (term, ty))| match ty {
+        Some(ty) => Term::Ann(AnnTerm::new(term.ptr(), ty, loc)),
+        None => term,
+    })(i)
+}
+
+fn seq_term<'a>(i: Loc<'a>, fxs: &Fixities) -> ParseRes<'a, Term<'a>> {
     let res = tuple((
         expect(TokenKind::LParen),
-        separated_list0(expect(TokenKind::Comma), term),
+        separated_list0(expect(TokenKind::Comma), |i| ann_term(i, fxs)),
         expect(TokenKind::RParen),
     ));
 
@@ -88,73 +102,234 @@ fn seq_term(i: Loc) -> ParseRes<Term> {
     })(i)
 }
 
-fn con_term(i: Loc) -> ParseRes<Term> {
-    let res = tuple((udent, seq_term));
+fn con_term<'a>(i: Loc<'a>, fxs: &Fixities) -> ParseRes<'a, Term<'a>> {
+    let res = tuple((udent, |i| seq_term(i, fxs)));
 
     map(with_loc(res), |(loc, (name, body))| {
         Term::Con(ConTerm::new(name, body.ptr(), loc))
     })(i)
 }
 
+// `_` only separates two digits, any other one is kept to fail the conversion
+fn strip_separators(val: &str, radix: u32) -> String {
+    let chars = val.chars().collect::<Vec<_>>();
+    let is_digit = |idx: Option<usize>| {
+        idx.and_then(|idx| chars.get(idx))
+            .is_some_and(|c| c.is_digit(radix))
+    };
+
+    chars
+        .iter()
+        .enumerate()
+        .filter(|(idx, c)| **c != '_' || !(is_digit(idx.checked_sub(1)) && is_digit(Some(idx + 1))))
+        .map(|(_, c)| *c)
+        .collect()
+}
+
+// `0x`, `0o` and `0b` choose the radix
+fn num_lit<'a>(tok: Token<'a>, sign: &str, loc: Loc<'a>) -> Result<Lit<'a>, ParseErr> {
+    let val = tok.loc.val();
+
+    match tok.kind {
+        TokenKind::Int => {
+            let (radix, digits) = match val.get(..2) {
+                Some("0x" | "0X") => (16, &val[2..]),
+                Some("0o" | "0O") => (8, &val[2..]),
+                Some("0b" | "0B") => (2, &val[2..]),
+                _ => (10, val),
+            };
+            let digits = strip_separators(digits, radix);
+            i64::from_str_radix(&format!("{sign}{digits}"), radix)
+                .map(|x| Lit::Int(IntLit::new(x, loc)))
+                .map_err(|e| ParseErr::BadIntLiteral(loc.into_span(), e))
+        }
+        _ => format!("{sign}{}", strip_separators(val, 10))
+            .parse::<f64>()
+            .map(|x| Lit::Double(DoubleLit::new(x, loc)))
+            .map_err(|e| ParseErr::BadDoubleLiteral(loc.into_span(), e)),
+    }
+}
+
 fn int_lit(i: Loc) -> ParseRes<Lit> {
     let (rem, tok) = expect(TokenKind::Int)(i)?;
 
-    match tok.loc.val().parse::<i64>() {
-        Ok(x) => Ok((rem, Lit::Int(IntLit::new(x, tok.loc)))),
-        Err(e) => ParseErr::BadIntLiteral(tok.loc.into_span(), e).failure(),
+    match num_lit(tok, "", tok.loc) {
+        Ok(lit) => Ok((rem, lit)),
+        Err(e) => e.failure(),
     }
 }
 
 fn double_lit(i: Loc) -> ParseRes<Lit> {
     let (rem, tok) = expect(TokenKind::Double)(i)?;
 
-    match tok.loc.val().parse::<f64>() {
-        Ok(x) => Ok((rem, Lit::Double(DoubleLit::new(x, tok.loc)))),
-        Err(e) => ParseErr::BadDoubleLiteral(tok.loc.into_span(), e).failure(),
+    match num_lit(tok, "", tok.loc) {
+        Ok(lit) => Ok((rem, lit)),
+        Err(e) => e.failure(),
+    }
+}
+
+fn minus(i: Loc) -> ParseRes<Name> {
+    verify(opr_name, |name: &Name| name.val == "-")(i)
+}
+
+// the sign is folded into the literal, so the smallest integer can be written
+fn signed<'a, P>(num: P) -> impl FnMut(Loc<'a>) -> ParseRes<'a, Lit<'a>>
+where
+    P: Parser<Loc<'a>, Token<'a>, ParseErr>,
+{
+    let mut res = tuple((minus, num));
+
+    move |i: Loc<'a>| {
+        let (rem, (sign, tok)) = res.parse(i)?;
+
+        match num_lit(tok, "-", pair_loc(i, sign.loc, tok.loc)) {
+            Ok(lit) => Ok((rem, lit)),
+            Err(e) => e.failure(),
+        }
+    }
+}
+
+fn neg_lit(i: Loc) -> ParseRes<Lit> {
+    signed(alt((expect(TokenKind::Int), expect(TokenKind::Double))))(i)
+}
+
+// only integer literals can be matched against
+fn neg_int_lit(i: Loc) -> ParseRes<Lit> {
+    signed(expect(TokenKind::Int))(i)
+}
+
+fn unescape(loc: Loc) -> Result<String, ParseErr> {
+    let raw = &loc.val()[1..loc.len() - 1];
+    let mut res = String::with_capacity(raw.len());
+    let mut it = raw.char_indices().peekable();
+
+    while let Some((begin, c)) = it.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+
+        let decoded = match it.next() {
+            Some((_, 'n')) => Some('\n'),
+            Some((_, 't')) => Some('\t'),
+            Some((_, 'r')) => Some('\r'),
+            Some((_, '0')) => Some('\0'),
+            Some((_, '\\')) => Some('\\'),
+            Some((_, '"')) => Some('"'),
+            Some((_, 'u')) if it.next_if(|(_, c)| *c == '{').is_some() => {
+                let mut code = String::new();
+                while let Some((_, c)) = it.next_if(|(_, c)| c.is_ascii_hexdigit()) {
+                    code.push(c);
+                }
+                match (it.next_if(|(_, c)| *c == '}'), code.len()) {
+                    (Some(_), 1..=6) => {
+                        u32::from_str_radix(&code, 16).ok().and_then(char::from_u32)
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+
+        match decoded {
+            Some(c) => res.push(c),
+            None => {
+                // the escape sequence spans up to the first character not taken
+                let end = it.peek().map_or(raw.len(), |(idx, _)| *idx);
+                let span = Span::new(SourceOffset::from(loc.begin() + 1 + begin), end - begin);
+                return Err(ParseErr::BadEscape(span, raw[begin..end].to_string()));
+            }
+        }
     }
+
+    Ok(res)
 }
 
 fn str_lit(i: Loc) -> ParseRes<Lit> {
-    let res = expect(TokenKind::Str);
+    let (rem, tok) = expect(TokenKind::Str)(i)?;
 
-    map(res, |tok| {
-        let val = tok.loc.val();
-        Lit::Str(StrLit::new(&val[1..val.len() - 1], tok.loc))
-    })(i)
+    match unescape(tok.loc) {
+        Ok(val) => Ok((rem, Lit::Str(StrLit::new(val, tok.loc)))),
+        Err(e) => e.failure(),
+    }
+}
+
+fn bool_lit(i: Loc) -> ParseRes<Lit> {
+    match token(i)? {
+        (r, tok) if tok.kind == TokenKind::True => Ok((r, Lit::Bool(BoolLit::new(true, tok.loc)))),
+        (r, tok) if tok.kind == TokenKind::False => {
+            Ok((r, Lit::Bool(BoolLit::new(false, tok.loc))))
+        }
+        (_, tok) => ParseErr::UnexpectedToken(tok.loc.into_span(), TokenKind::True, tok.kind).err(),
+    }
 }
 
 fn lit(i: Loc) -> ParseRes<Lit> {
-    alt((int_lit, double_lit, str_lit))(i)
+    alt((int_lit, double_lit, str_lit, bool_lit))(i)
 }
 
-fn at_term(i: Loc) -> ParseRes<Term> {
+// the given operand of a section is a single operand, `(+ a * b)` needs parentheses
+fn section<'a>(i: Loc<'a>, fxs: &Fixities) -> ParseRes<'a, Term<'a>> {
+    let left = map(tuple((|i| operand(i, fxs), opr_name)), |(left, name)| {
+        (Some(left), name, None)
+    });
+    let right = map(
+        tuple((opr_name, opt(|i| operand(i, fxs)))),
+        |(name, right)| (None, name, right),
+    );
+    let res = tuple((
+        expect(TokenKind::LParen),
+        alt((right, left)),
+        expect(TokenKind::RParen),
+    ));
+
+    map(with_loc(res), |(loc, (_, (left, name, right), _))| {
+        Term::Section(Section::new(
+            left.map(Term::ptr),
+            name,
+            right.map(Term::ptr),
+            loc,
+        ))
+    })(i)
+}
+
+fn at_term<'a>(i: Loc<'a>, fxs: &Fixities) -> ParseRes<'a, Term<'a>> {
     alt((
         map(lit, Term::Lit),
         map(ident, Term::Var),
-        con_term,
-        seq_term,
+        |i| con_term(i, fxs),
+        |i| seq_term(i, fxs),
+        |i| section(i, fxs),
     ))(i)
 }
 
-fn abs(i: Loc) -> ParseRes<Term> {
+fn abs<'a>(i: Loc<'a>, fxs: &Fixities) -> ParseRes<'a, Term<'a>> {
     let res = tuple((
         expect(TokenKind::Backslash),
-        many1(ident),
+        many1(at_tpl),
         expect(TokenKind::Arrow),
-        term,
+        |i| term(i, fxs),
     ));
 
     map(res, |(_, params, _, body)| {
         let second = body.loc();
         params.into_iter().rfold(body, |acc, p| {
-            let loc = pair_loc(i, p.loc, second);
+            let loc = pair_loc(i, p.loc(), second);
             Term::Abs(Abs::new(p, acc.ptr(), loc))
         })
     })(i)
 }
 
-fn app(i: Loc) -> ParseRes<Term> {
-    let res = tuple((at_term, many1(at_term)));
+// `f x y = body` stands for `f = \x y -> body`, the lambdas are located at the name
+#[inline]
+fn func<'a>(loc: Loc<'a>, params: Vec<Tpl<'a>>, body: Term<'a>) -> Term<'a> {
+    params
+        .into_iter()
+        .rfold(body, |acc, p| Term::Abs(Abs::new(p, acc.ptr(), loc)))
+}
+
+fn app<'a>(i: Loc<'a>, fxs: &Fixities) -> ParseRes<'a, Term<'a>> {
+    let res = tuple((|i| at_term(i, fxs), many1(|i| at_term(i, fxs))));
 
     map(res, |(head, children)| {
         children.into_iter().fold(head, |acc, x| {
@@ -164,27 +339,109 @@ fn app(i: Loc) -> ParseRes<Term> {
     })(i)
 }
 
-fn app_term(i: Loc) -> ParseRes<Term> {
-    alt((app, at_term))(i)
+fn app_term<'a>(i: Loc<'a>, fxs: &Fixities) -> ParseRes<'a, Term<'a>> {
+    alt((|i| app(i, fxs), |i| at_term(i, fxs)))(i)
+}
+
+// `-f x` negates the application, `f -x` subtracts from `f`
+fn neg<'a>(i: Loc<'a>, fxs: &Fixities) -> ParseRes<'a, Term<'a>> {
+    let res = tuple((minus, |i| operand(i, fxs)));
+
+    map(with_loc(res), |(loc, (_, term))| {
+        Term::Neg(Neg::new(term.ptr(), loc))
+    })(i)
+}
+
+fn operand<'a>(i: Loc<'a>, fxs: &Fixities) -> ParseRes<'a, Term<'a>> {
+    alt((
+        |i| app_term(i, fxs),
+        |i| abs(i, fxs),
+        map(neg_lit, Term::Lit),
+        |i| neg(i, fxs),
+    ))(i)
+}
+
+#[inline]
+fn next_opr<'a>(i: Loc<'a>, fxs: &Fixities) -> Option<(Loc<'a>, Name<'a>, Fixity)> {
+    opr_name(i).ok().map(|(r, name)| {
+        let fx = fixity(fxs, name.val);
+        (r, name, fx)
+    })
+}
+
+// precedence climbing over `operand (opr operand)*`
+fn climb<'a>(
+    i: Loc<'a>,
+    mut left: Term<'a>,
+    mut rem: Loc<'a>,
+    min_prec: u8,
+    fxs: &Fixities,
+) -> ParseRes<'a, Term<'a>> {
+    while let Some((r, name, fx)) = next_opr(rem, fxs).filter(|(_, _, fx)| fx.prec >= min_prec) {
+        let (r, mut right) = operand(r, fxs)?;
+        rem = r;
+
+        while let Some((_, next_name, next)) = next_opr(rem, fxs) {
+            if next.prec < fx.prec {
+                break;
+            }
+            if next.prec == fx.prec {
+                match (fx.assoc, next.assoc) {
+                    (Assoc::Left, Assoc::Left) => break,
+                    (Assoc::Right, Assoc::Right) => (),
+                    _ => {
+                        return ParseErr::AmbiguousOpr(
+                            next_name.loc.into_span(),
+                            name.val.to_string(),
+                            next_name.val.to_string(),
+                        )
+                        .failure()
+                    }
+                }
+            }
+            let tighter = next.prec > fx.prec;
+            (rem, right) = climb(i, right, rem, fx.prec + tighter as u8, fxs)?;
+        }
+
+        let loc = pair_loc(i, left.loc(), right.loc());
+        left = Term::Opr(Opr::new(left.ptr(), name, right.ptr(), loc));
+    }
+
+    Ok((rem, left))
 }
 
-fn opr(i: Loc) -> ParseRes<Term> {
-    let res = tuple((app_term, opr_name, infix_term));
+fn opr<'a>(i: Loc<'a>, fxs: &Fixities) -> ParseRes<'a, Term<'a>> {
+    let (rem, left) = operand(i, fxs)?;
+    climb(i, left, rem, 0, fxs)
+}
 
-    map(with_loc(res), |(loc, (left, name, right))| {
-        Term::Opr(Opr::new(left.ptr(), name, right.ptr(), loc))
+fn func_part<'a>(i: Loc<'a>, fxs: &Fixities) -> ParseRes<'a, (Tpl<'a>, Term<'a>)> {
+    let res = tuple((
+        ident,
+        many1(at_tpl),
+        expect(TokenKind::Assign),
+        |i| rhs(i, fxs),
+        expect(TokenKind::Semicolon),
+    ));
+
+    map(res, |(name, params, _, body, _)| {
+        let term = func(name.loc, params, body);
+        (Tpl::Var(name), term)
     })(i)
 }
 
-fn let_part(i: Loc) -> ParseRes<(Tpl, Term)> {
+fn let_part<'a>(i: Loc<'a>, fxs: &Fixities) -> ParseRes<'a, (Tpl<'a>, Term<'a>)> {
     let res = tuple((
-        tpl,
+        ann_tpl,
         expect(TokenKind::Assign),
-        term,
+        |i| rhs(i, fxs),
         expect(TokenKind::Semicolon),
     ));
 
-    map(res, |(tpl, _, body, _)| (tpl, body))(i)
+    alt((
+        |i| func_part(i, fxs),
+        map(res, |(tpl, _, body, _)| (tpl, body)),
+    ))(i)
 }
 
 #[derive(Debug)]
@@ -201,26 +458,39 @@ fn let_kind(i: Loc) -> ParseRes<LetKind> {
     }
 }
 
-fn let_term(i: Loc) -> ParseRes<Term> {
-    let res = tuple((let_kind, many1(let_part), expect(TokenKind::In), term));
+// several bindings are bound at once as a tuple
+#[inline]
+fn join_parts<'a>(parts: Vec<(Tpl<'a>, Term<'a>)>) -> (Tpl<'a>, Term<'a>) {
+    let (tpls, terms): (Vec<_>, Vec<_>) = parts.into_iter().unzip();
+
+    let tpl = tpls
+        .into_iter()
+        .reduce(|a, b| {
+            let loc = b.loc();
+            Tpl::Pair(PairTpl::new(a.ptr(), b.ptr(), loc))
+        })
+        .unwrap(); // because we always have at least one let_part
+    let term = terms
+        .into_iter()
+        .reduce(|a, b| {
+            let loc = b.loc();
+            Term::Pair(PairTerm::new(a.ptr(), b.ptr(), loc))
+        })
+        .unwrap(); // because we always have at least one let_part
+
+    (tpl, term)
+}
+
+fn let_term<'a>(i: Loc<'a>, fxs: &Fixities) -> ParseRes<'a, Term<'a>> {
+    let res = tuple((
+        let_kind,
+        many1(|i| let_part(i, fxs)),
+        expect(TokenKind::In),
+        |i| term(i, fxs),
+    ));
 
     map(with_loc(res), |(loc, (kind, parts, _, body))| {
-        let (tpls, terms): (Vec<_>, Vec<_>) = parts.into_iter().unzip();
-
-        let tpl = tpls
-            .into_iter()
-            .reduce(|a, b| {
-                let loc = b.loc();
-                Tpl::Pair(PairTpl::new(a.ptr(), b.ptr(), loc))
-            })
-            .unwrap(); // because we always have at least one let_part
-        let term = terms
-            .into_iter()
-            .reduce(|a, b| {
-                let loc = b.loc();
-                Term::Pair(PairTerm::new(a.ptr(), b.ptr(), loc))
-            })
-            .unwrap(); // because we always have at least one let_part
+        let (tpl, term) = join_parts(parts);
 
         match kind {
             LetKind::NonRec => Term::Let(Let::new(tpl, term.ptr(), body.ptr(), loc)),
@@ -229,27 +499,27 @@ fn let_term(i: Loc) -> ParseRes<Term> {
     })(i)
 }
 
-fn branch(i: Loc) -> ParseRes<Branch> {
+fn branch<'a>(i: Loc<'a>, fxs: &Fixities) -> ParseRes<'a, Branch<'a>> {
     let res = tuple((
         expect(TokenKind::Pipe),
-        udent,
-        tpl,
+        cut(tpl),
+        opt(preceded(expect(TokenKind::If), |i| term(i, fxs))),
         expect(TokenKind::Arrow),
-        term,
+        |i| term(i, fxs),
         expect(TokenKind::Semicolon),
     ));
 
-    map(with_loc(res), |(loc, (_, con, tpl, _, term, _))| {
-        Branch::new(con, tpl, term, loc)
+    map(with_loc(res), |(loc, (_, tpl, guard, _, term, _))| {
+        Branch::new(tpl, guard, term, loc)
     })(i)
 }
 
-fn case(i: Loc) -> ParseRes<Term> {
+fn case<'a>(i: Loc<'a>, fxs: &Fixities) -> ParseRes<'a, Term<'a>> {
     let res = tuple((
         expect(TokenKind::Case),
-        term,
+        |i| term(i, fxs),
         expect(TokenKind::Of),
-        many1(branch),
+        many1(|i| branch(i, fxs)),
     ));
 
     map(with_loc(res), |(loc, (_, cond, _, branches))| {
@@ -257,14 +527,14 @@ fn case(i: Loc) -> ParseRes<Term> {
     })(i)
 }
 
-fn cond(i: Loc) -> ParseRes<Term> {
+fn cond<'a>(i: Loc<'a>, fxs: &Fixities) -> ParseRes<'a, Term<'a>> {
     let res = tuple((
         expect(TokenKind::If),
-        term,
+        |i| term(i, fxs),
         expect(TokenKind::Then),
-        term,
+        |i| term(i, fxs),
         expect(TokenKind::Else),
-        term,
+        |i| term(i, fxs),
     ));
 
     map(with_loc(res), |(loc, (_, cond, _, left, _, right))| {
@@ -272,22 +542,109 @@ fn cond(i: Loc) -> ParseRes<Term> {
     })(i)
 }
 
-fn infix_term(i: Loc) -> ParseRes<Term> {
-    alt((opr, app_term, abs))(i)
+fn infix_term<'a>(i: Loc<'a>, fxs: &Fixities) -> ParseRes<'a, Term<'a>> {
+    opr(i, fxs)
+}
+
+// `do { a; b; c }` evaluates `a` and `b` for their side effects and returns `c`
+// `{-` always opens a comment, so a block starting with a negation needs `do { -a; b }`
+fn do_term<'a>(i: Loc<'a>, fxs: &Fixities) -> ParseRes<'a, Term<'a>> {
+    let res = tuple((
+        expect(TokenKind::Do),
+        expect(TokenKind::LBrace),
+        separated_list1(expect(TokenKind::Semicolon), |i| term(i, fxs)),
+        expect(TokenKind::RBrace),
+    ));
+
+    map(res, |(_, _, mut terms, _)| {
+        // because we always have at least one term
+        let last = terms.pop().unwrap();
+        terms.into_iter().rfold(last, |acc, x| {
+            let loc = pair_loc(i, x.loc(), acc.loc());
+            Term::Seq(Seq::new(x.ptr(), acc.ptr(), loc))
+        })
+    })(i)
+}
+
+fn where_parts<'a>(i: Loc<'a>, fxs: &Fixities) -> ParseRes<'a, Vec<(Tpl<'a>, Term<'a>)>> {
+    let res = tuple((
+        expect(TokenKind::Where),
+        expect(TokenKind::LBrace),
+        many1(|i| let_part(i, fxs)),
+        expect(TokenKind::RBrace),
+    ));
+
+    map(res, |(_, _, parts, _)| parts)(i)
+}
+
+// `body where { parts }` stands for `letrec parts in body`, split into groups
+// of mutually recursive parts: a letrec evaluates its bindings on every use,
+// so plain values referring to each other in one letrec would never finish
+fn where_term<'a>(loc: Loc<'a>, parts: Vec<(Tpl<'a>, Term<'a>)>, body: Term<'a>) -> Term<'a> {
+    let groups = tpl_groups(&parts);
+    let mut parts = parts.into_iter().map(Some).collect::<Vec<_>>();
+
+    groups.into_iter().rfold(body, |acc, group| {
+        let (tpl, term) = join_parts(
+            group
+                .idxs
+                .iter()
+                .filter_map(|idx| parts[*idx].take())
+                .collect(),
+        );
+
+        match group.rec {
+            true => Term::Letrec(Letrec::new(tpl, term.ptr(), acc.ptr(), loc)),
+            false => Term::Let(Let::new(tpl, term.ptr(), acc.ptr(), loc)),
+        }
+    })
+}
+
+fn term<'a>(i: Loc<'a>, fxs: &Fixities) -> ParseRes<'a, Term<'a>> {
+    alt((
+        |i| infix_term(i, fxs),
+        |i| cond(i, fxs),
+        |i| case(i, fxs),
+        |i| let_term(i, fxs),
+        |i| do_term(i, fxs),
+    ))(i)
 }
 
-fn term(i: Loc) -> ParseRes<Term> {
-    alt((infix_term, cond, case, let_term))(i)
+// the right-hand side of a binding, `where` scopes over all of it
+fn rhs<'a>(i: Loc<'a>, fxs: &Fixities) -> ParseRes<'a, Term<'a>> {
+    let res = tuple((|i| term(i, fxs), opt(|i| where_parts(i, fxs))));
+
+    map(with_loc(res), |(loc, (body, parts))| match parts {
+        Some(parts) => where_term(loc, parts, body),
+        None => body,
+    })(i)
 }
 
 fn param(i: Loc) -> ParseRes<Tpl> {
     map(ident, Tpl::Var)(i)
 }
 
+fn wild_tpl(i: Loc) -> ParseRes<Tpl> {
+    map(expect(TokenKind::Wild), |t| Tpl::Wild(WildTpl::new(t.loc)))(i)
+}
+
+fn lit_tpl(i: Loc) -> ParseRes<Tpl> {
+    map(alt((int_lit, neg_int_lit, str_lit)), Tpl::Lit)(i)
+}
+
+fn ann_tpl(i: Loc) -> ParseRes<Tpl> {
+    let res = tuple((tpl, opt(ann)));
+
+    map(with_loc(res), |(loc, (tpl, ty))| match ty {
+        Some(ty) => Tpl::Ann(AnnTpl::new(tpl.ptr(), ty, loc)),
+        None => tpl,
+    })(i)
+}
+
 fn seq_tpl(i: Loc) -> ParseRes<Tpl> {
     let res = tuple((
         expect(TokenKind::LParen),
-        separated_list0(expect(TokenKind::Comma), tpl),
+        separated_list0(expect(TokenKind::Comma), ann_tpl),
         expect(TokenKind::RParen),
     ));
 
@@ -303,12 +660,29 @@ fn seq_tpl(i: Loc) -> ParseRes<Tpl> {
     })(i)
 }
 
+// a constructor without an argument stands for the one holding `()`
+fn bare_con_tpl(i: Loc) -> ParseRes<Tpl> {
+    map(udent, |name| {
+        let loc = name.loc;
+        let body = Tpl::Empty(EmptyTpl::new(loc));
+        Tpl::Con(ConTpl::new(name, body.ptr(), loc))
+    })(i)
+}
+
 fn at_tpl(i: Loc) -> ParseRes<Tpl> {
-    alt((param, seq_tpl))(i)
+    alt((param, wild_tpl, lit_tpl, bare_con_tpl, seq_tpl))(i)
+}
+
+fn con_tpl(i: Loc) -> ParseRes<Tpl> {
+    let res = tuple((udent, at_tpl));
+
+    map(with_loc(res), |(loc, (name, body))| {
+        Tpl::Con(ConTpl::new(name, body.ptr(), loc))
+    })(i)
 }
 
 fn as_tpl(i: Loc) -> ParseRes<Tpl> {
-    let res = tuple((ident, expect(TokenKind::As), at_tpl));
+    let res = tuple((ident, expect(TokenKind::As), alt((con_tpl, at_tpl))));
 
     map(with_loc(res), |(loc, (name, _, tpl))| {
         Tpl::As(AsTpl::new(name, tpl.ptr(), loc))
@@ -316,47 +690,179 @@ fn as_tpl(i: Loc) -> ParseRes<Tpl> {
 }
 
 fn tpl(i: Loc) -> ParseRes<Tpl> {
-    alt((as_tpl, at_tpl))(i)
+    alt((as_tpl, con_tpl, at_tpl))(i)
+}
+
+fn var_type(i: Loc) -> ParseRes<TypeExpr> {
+    map(ident, TypeExpr::Var)(i)
+}
+
+fn con_type(i: Loc) -> ParseRes<TypeExpr> {
+    map(with_loc(udent), |(loc, name)| {
+        TypeExpr::Con(ConTypeExpr::new(name, Vec::new(), loc))
+    })(i)
 }
 
-fn con(i: Loc) -> ParseRes<Con> {
-    let res = tuple((udent, many0(udent)));
+fn seq_type(i: Loc) -> ParseRes<TypeExpr> {
+    let res = tuple((
+        expect(TokenKind::LParen),
+        separated_list0(expect(TokenKind::Comma), type_expr),
+        expect(TokenKind::RParen),
+    ));
+
+    map(with_loc(res), |(loc, (_, exprs, _))| {
+        let mut it = exprs.into_iter();
+        match it.next() {
+            Some(init) => it.fold(init, |acc, x| {
+                let (first, second) = (acc.loc(), x.loc());
+                TypeExpr::Pair(PairTypeExpr::new(
+                    acc.ptr(),
+                    x.ptr(),
+                    pair_loc(i, first, second),
+                ))
+            }),
+            None => TypeExpr::Empty(EmptyTypeExpr::new(loc)),
+        }
+    })(i)
+}
+
+fn at_type(i: Loc) -> ParseRes<TypeExpr> {
+    alt((var_type, con_type, seq_type))(i)
+}
+
+fn app_type(i: Loc) -> ParseRes<TypeExpr> {
+    let res = tuple((udent, many1(at_type)));
 
     map(with_loc(res), |(loc, (name, args))| {
-        Con::new(name, args, loc)
+        TypeExpr::Con(ConTypeExpr::new(name, args, loc))
     })(i)
 }
 
-fn type_def(i: Loc) -> ParseRes<TypeDef> {
+fn func_type(i: Loc) -> ParseRes<TypeExpr> {
     let res = tuple((
+        alt((app_type, at_type)),
+        expect(TokenKind::Arrow),
+        type_expr,
+    ));
+
+    map(with_loc(res), |(loc, (from, _, into))| {
+        TypeExpr::Func(FuncTypeExpr::new(from.ptr(), into.ptr(), loc))
+    })(i)
+}
+
+fn type_expr(i: Loc) -> ParseRes<TypeExpr> {
+    alt((func_type, app_type, at_type))(i)
+}
+
+// without parentheses only the declared parameters are accepted as type variables,
+// otherwise the body of the program could be taken for constructor arguments
+fn con_arg<'a, 'p>(params: &'p [Name<'a>]) -> impl Fn(Loc<'a>) -> ParseRes<'a, TypeExpr<'a>> + 'p {
+    move |i| match var_type(i)? {
+        (_, TypeExpr::Var(name)) if params.iter().all(|p| p.val != name.val) => {
+            ParseErr::UnexpectedToken(name.loc.into_span(), TokenKind::Udent, TokenKind::Ident)
+                .err()
+        }
+        res => Ok(res),
+    }
+}
+
+fn con<'a, 'p>(params: &'p [Name<'a>]) -> impl FnMut(Loc<'a>) -> ParseRes<'a, Con<'a>> + 'p {
+    let res = tuple((udent, many0(alt((con_arg(params), con_type, seq_type)))));
+
+    map(with_loc(res), |(loc, (name, args))| {
+        Con::new(name, args, loc)
+    })
+}
+
+fn type_def(i: Loc) -> ParseRes<TypeDef> {
+    let (i, _) = trivia(i)?;
+    let (rem, (_, name, params, _)) = tuple((
         expect(TokenKind::Data),
         udent,
+        many0(ident),
         expect(TokenKind::Assign),
-        separated_list0(expect(TokenKind::Pipe), con),
+    ))(i)?;
+    let (rem, cons) = separated_list0(expect(TokenKind::Pipe), con(&params))(rem)?;
+
+    let loc = i.take(i.offset(&rem));
+    Ok((rem, TypeDef::new(name, params, cons, loc)))
+}
+
+fn assoc(i: Loc) -> ParseRes<Assoc> {
+    match token(i)? {
+        (r, tok) if tok.kind == TokenKind::Infixl => Ok((r, Assoc::Left)),
+        (r, tok) if tok.kind == TokenKind::Infixr => Ok((r, Assoc::Right)),
+        (_, tok) => {
+            ParseErr::UnexpectedToken(tok.loc.into_span(), TokenKind::Infixl, tok.kind).err()
+        }
+    }
+}
+
+fn prec(i: Loc) -> ParseRes<u8> {
+    let (rem, tok) = expect(TokenKind::Int)(i)?;
+
+    match tok.loc.val().parse::<u8>() {
+        Ok(x) if x <= 9 => Ok((rem, x)),
+        _ => ParseErr::BadPrecedence(tok.loc.into_span()).failure(),
+    }
+}
+
+fn opr_def<'a>(i: Loc<'a>, fxs: &Fixities) -> ParseRes<'a, OprDef<'a>> {
+    let res = tuple((
+        assoc,
+        prec,
+        opr_name,
+        expect(TokenKind::Assign),
+        cut(tuple((|i| rhs(i, fxs), expect(TokenKind::Semicolon)))),
     ));
-    map(with_loc(res), |(loc, (_, name, _, cons))| {
-        TypeDef::new(name, cons, loc)
+
+    map(with_loc(res), |(loc, (assoc, prec, name, _, (term, _)))| {
+        OprDef::new(Fixity::new(assoc, prec), name, term, loc)
     })(i)
 }
 
-fn def(i: Loc) -> ParseRes<Def> {
-    alt((map(type_def, Def::TypeDef),))(i)
+// once the head is recognised, an error in the body can't mean it's not a definition
+fn val_def<'a>(i: Loc<'a>, fxs: &Fixities) -> ParseRes<'a, ValDef<'a>> {
+    let res = tuple((
+        ident,
+        many0(at_tpl),
+        expect(TokenKind::Assign),
+        cut(tuple((|i| rhs(i, fxs), expect(TokenKind::Semicolon)))),
+    ));
+
+    map(with_loc(res), |(loc, (name, params, _, (body, _)))| {
+        let term = func(name.loc, params, body);
+        ValDef::new(name, term, loc)
+    })(i)
+}
+
+fn def<'a>(i: Loc<'a>, fxs: &Fixities) -> ParseRes<'a, Def<'a>> {
+    alt((
+        map(type_def, Def::TypeDef),
+        map(|i| opr_def(i, fxs), Def::OprDef),
+        map(|i| val_def(i, fxs), Def::ValDef),
+    ))(i)
 }
 
-fn ast(i: Loc) -> ParseRes<Ast> {
-    let (i, _) = multispace0(i)?;
-    let res = tuple((many0(def), term));
+fn ast<'a>(i: Loc<'a>, fxs: &Fixities) -> ParseRes<'a, Ast<'a>> {
+    let (i, _) = trivia(i)?;
+    let res = tuple((many0(|i| def(i, fxs)), opt(|i| rhs(i, fxs))));
 
     let (rem, ast) = map(with_loc(res), |(loc, (defs, body))| {
+        // without the final expression the program evaluates `main`
+        let body = body.unwrap_or_else(|| {
+            let end = loc.take_split(loc.len()).0;
+            Term::Var(Name::new("main", end))
+        });
         Ast::new(defs, body, loc)
     })(i)?;
 
-    let (rem, _) = tuple((multispace0, eof))(rem)?;
+    let (rem, _) = tuple((trivia, eof))(rem)?;
     Ok((rem, ast))
 }
 
-pub fn parse(s: &str) -> Result<Ast<'_>, ParseErr> {
-    match ast(Loc::new(s)) {
+fn run<'a>(s: &'a str, fxs: &Fixities) -> Result<Ast<'a>, ParseErr> {
+    match ast(Loc::new(s), fxs) {
         Ok((_, ast)) => Ok(ast),
         Err(nom::Err::Error(e)) => Err(e),
         Err(nom::Err::Failure(e)) => Err(e),
@@ -364,12 +870,37 @@ pub fn parse(s: &str) -> Result<Ast<'_>, ParseErr> {
     }
 }
 
+// operators may be used before they are declared, so the first pass finds
+// the declarations only. It takes all the operators as the same, so the
+// precedence can't make it fail where the second pass wouldn't
+pub fn parse(s: &str) -> Result<Ast<'_>, ParseErr> {
+    let fxs = declared(&run(s, &Fixities::new())?);
+    run(s, &fxs)
+}
+
 #[cfg(test)]
 mod tests {
     use core::panic;
 
     use super::*;
 
+    fn show(term: &Term) -> String {
+        match term {
+            Term::Var(name) => name.val.to_string(),
+            Term::Lit(lit) => lit.loc().val().to_string(),
+            Term::Opr(opr) => format!("({} {} {})", show(&opr.left), *opr.name, show(&opr.right)),
+            Term::App(app) => format!("({} {})", show(&app.head), show(&app.body)),
+            term => panic!("Unexpected term: {term:?}"),
+        }
+    }
+
+    fn assert_body(input: &str, expected: &str) {
+        match parse(input) {
+            Ok(ast) => assert_eq!(expected, show(&ast.body)),
+            Err(e) => panic!("Unexpected error: {e:?}"),
+        }
+    }
+
     #[test]
     fn ok_valid_ident() {
         let input = "valid_ident";
@@ -426,10 +957,45 @@ mod tests {
         }
     }
 
+    fn assert_str_lit(input: &str, expected: &str) {
+        match lit(Loc::new(input)) {
+            Ok((_, Lit::Str(lit))) => assert_eq!(expected, lit.val),
+            Ok((_, lit)) => panic!("Unexpected literal: {lit:?}"),
+            Err(e) => panic!("Unexpected error: {e:?}"),
+        }
+    }
+
+    fn assert_bad_escape(input: &str, begin: usize, len: usize) {
+        match lit(Loc::new(input)) {
+            Ok(res) => panic!("This test should fail, but got {res:?}"),
+            Err(nom::Err::Failure(e @ ParseErr::BadEscape(..))) => {
+                assert_eq!((begin, len), (e.begin(), e.span().len()))
+            }
+            Err(e) => panic!("Unexpected error: {e:?}"),
+        }
+    }
+
+    #[test]
+    fn ok_str_lit_escapes() {
+        assert_str_lit(r#""plain""#, "plain");
+        assert_str_lit(r#""a\tb\nc""#, "a\tb\nc");
+        assert_str_lit(r#""say \"hi\" \\o/""#, r#"say "hi" \o/"#);
+        assert_str_lit(r#""\u{41}\u{1F600}\u{43b}""#, "A\u{1F600}\u{43b}");
+    }
+
+    #[test]
+    fn err_str_lit_bad_escapes() {
+        assert_bad_escape(r#""ab\q""#, 3, 2);
+        assert_bad_escape(r#""\u{110000}""#, 1, 10);
+        assert_bad_escape(r#""\u{}""#, 1, 4);
+        assert_bad_escape(r#""\u{41""#, 1, 5);
+        assert_bad_escape(r#""x\u41""#, 2, 2);
+    }
+
     #[test]
     fn test_let_term_parser() {
         let input = "let x = 5; in x";
-        let res = let_term(Loc::new(input));
+        let res = let_term(Loc::new(input), &Fixities::new());
 
         if let Err(e) = res {
             panic!("Unexpected error: {e:?}")
@@ -439,7 +1005,7 @@ mod tests {
     #[test]
     fn ok_cond_parser() {
         let input = "if someName == 123 then 1 else 0";
-        let res = cond(Loc::new(input));
+        let res = cond(Loc::new(input), &Fixities::new());
 
         if let Err(e) = res {
             panic!("Unexpected error: {e:?}")
@@ -462,6 +1028,636 @@ mod tests {
         }
     }
 
+    #[test]
+    fn ok_ast_with_comments() {
+        let input = r#"
+            -- the entry point
+            let x = {- inline {- nested -} -} 5; in
+            x -- trailing
+            {- and the end -}
+        "#;
+        let res = parse(input);
+
+        if let Err(e) = res {
+            panic!("Unexpected error: {e:?}")
+        }
+    }
+
+    #[test]
+    fn ok_fixity_declarations() {
+        let input = r#"
+            infixl 6 <+> = \a b -> a + b;
+            infixr 7 <*> = \a b -> a * b;
+            a <+> b <*> c <*> d <+> e
+        "#;
+        assert_body(input, "((a <+> (b <*> (c <*> d))) <+> e)");
+    }
+
+    #[test]
+    fn ok_fixity_declared_after_use() {
+        let input = r#"
+            infixl 1 |> = \x f -> f x <| 1;
+            infixr 0 <| = \f x -> f x;
+            x |> f |> g
+        "#;
+        assert_body(input, "((x |> f) |> g)");
+    }
+
+    #[test]
+    fn ok_fixity_declared_after_mixed_use() {
+        let input = r#"
+            main = x <> y ++ z;
+            infixr 5 <> = \a b -> a ++ b;
+            main
+        "#;
+        match parse(input) {
+            Ok(ast) => match &ast.defs[0] {
+                Def::ValDef(def) => assert_eq!("(x <> (y ++ z))", show(&def.term)),
+                def => panic!("Expected value definition, got {def:?}"),
+            },
+            Err(e) => panic!("Unexpected error: {e:?}"),
+        }
+    }
+
+    #[test]
+    fn err_ambiguous_oprs() {
+        let input = r#"
+            main = x <> y ++ z;
+            infixl 5 <> = \a b -> a ++ b;
+            main
+        "#;
+        match parse(input) {
+            Err(ParseErr::AmbiguousOpr(span, ..)) => assert_eq!(27, span.offset()),
+            res => panic!("Expected ambiguous operators, got {res:?}"),
+        }
+    }
+
+    #[test]
+    fn ok_application_binds_tighter() {
+        let input = r#"
+            infixl 6 <+> = \a b -> a;
+            f x <+> g y <+> z
+        "#;
+        assert_body(input, "(((f x) <+> (g y)) <+> z)");
+    }
+
+    #[test]
+    fn ok_left_assoc_oprs() {
+        assert_body("a - b - c + d", "(((a - b) - c) + d)");
+        assert_body("a /% b *% c", "((a /% b) *% c)");
+    }
+
+    #[test]
+    fn ok_right_assoc_oprs() {
+        assert_body(r#"a ++ "b" ++ c"#, r#"(a ++ ("b" ++ c))"#);
+    }
+
+    #[test]
+    fn ok_opr_precedence() {
+        assert_body("x * 2 + 1", "((x * 2) + 1)");
+        assert_body("1 + x * 2", "(1 + (x * 2))");
+        assert_body("a + b * c == d - e / f", "((a + (b * c)) == (d - (e / f)))");
+        assert_body("s ++ t =&= u", "((s ++ t) =&= u)");
+    }
+
+    #[test]
+    fn ok_user_and_predef_oprs() {
+        let input = r#"
+            infixr 3 <&> = \a b -> a * b;
+            a < b <&> c + 1 > d <&> e
+        "#;
+        assert_body(input, "((a < b) <&> (((c + 1) > d) <&> e))");
+    }
+
+    #[test]
+    fn ok_bool_oprs() {
+        assert_body(
+            "a < 1 || b && not c || False",
+            "((a < 1) || ((b && (not c)) || False))",
+        );
+    }
+
+    #[test]
+    fn ok_parametric_data() {
+        let input = r#"
+            data Pair a b = Pair a b
+            data List a = Nil | Cons a (List (Pair a Int))
+            print "x"
+        "#;
+
+        match parse(input) {
+            Ok(ast) => {
+                let Def::TypeDef(def) = &ast.defs[1] else {
+                    panic!("Unexpected def: {:?}", ast.defs[1]);
+                };
+                assert_eq!(
+                    vec!["a"],
+                    def.params.iter().map(|p| p.val).collect::<Vec<_>>()
+                );
+                assert_eq!(2, def.cons[1].args.len());
+                assert_eq!("(print \"x\")", show(&ast.body));
+            }
+            Err(e) => panic!("Unexpected error: {e:?}"),
+        }
+    }
+
+    #[test]
+    fn ok_type_exprs() {
+        fn show_type(expr: &TypeExpr) -> String {
+            match expr {
+                TypeExpr::Empty(_) => "()".to_string(),
+                TypeExpr::Var(name) => name.val.to_string(),
+                TypeExpr::Con(expr) => {
+                    expr.args.iter().fold(expr.name.val.to_string(), |acc, a| {
+                        format!("{acc} {}", show_type(a))
+                    })
+                }
+                TypeExpr::Pair(expr) => {
+                    format!("({}, {})", show_type(&expr.left), show_type(&expr.right))
+                }
+                TypeExpr::Func(expr) => {
+                    format!("[{} -> {}]", show_type(&expr.from), show_type(&expr.into))
+                }
+            }
+        }
+
+        let input =
+            "data T a = T (Int -> Str) (a, Int, ()) ((a -> a) -> List a -> Int) (List (a, a))\n1";
+        match parse(input) {
+            Ok(ast) => {
+                let Def::TypeDef(def) = &ast.defs[0] else {
+                    panic!("Unexpected def: {:?}", ast.defs[0]);
+                };
+                let args = def.cons[0].args.iter().map(show_type).collect::<Vec<_>>();
+                assert_eq!(
+                    vec![
+                        "[Int -> Str]",
+                        "((a, Int), ())",
+                        "[[a -> a] -> [List a -> Int]]",
+                        "List (a, a)",
+                    ],
+                    args
+                );
+            }
+            Err(e) => panic!("Unexpected error: {e:?}"),
+        }
+    }
+
+    #[test]
+    fn ok_type_annotations() {
+        let input = r#"
+            let n : Int = 1;
+                (a, b : Str) : (Int, Str) = (1, "s");
+            in (\(x : Double) y -> (x : Double)) 1.0 n
+        "#;
+
+        match parse(input) {
+            Ok(ast) => {
+                let Term::Let(term) = &ast.body else {
+                    panic!("Unexpected term: {:?}", ast.body);
+                };
+                let Tpl::Pair(tpl) = &term.tpl else {
+                    panic!("Unexpected tpl: {:?}", term.tpl);
+                };
+                assert!(matches!(*tpl.left, Tpl::Ann(_)));
+                assert!(matches!(*tpl.right, Tpl::Ann(_)));
+            }
+            Err(e) => panic!("Unexpected error: {e:?}"),
+        }
+    }
+
+    #[test]
+    fn ok_nested_con_patterns() {
+        let input = r#"
+            case xs of
+            | Nil -> 0;
+            | Cons (x, r as Cons (y, Nil)) -> x + y;
+        "#;
+
+        match parse(input) {
+            Ok(ast) => {
+                let Term::Case(term) = &ast.body else {
+                    panic!("Unexpected term: {:?}", ast.body);
+                };
+                let Tpl::Con(tpl) = &term.branches[0].tpl else {
+                    panic!("Unexpected tpl: {:?}", term.branches[0].tpl);
+                };
+                assert!(matches!(*tpl.body, Tpl::Empty(_)));
+                let Tpl::Con(tpl) = &term.branches[1].tpl else {
+                    panic!("Unexpected tpl: {:?}", term.branches[1].tpl);
+                };
+                let Tpl::Pair(tpl) = &*tpl.body else {
+                    panic!("Unexpected tpl: {:?}", tpl.body);
+                };
+                assert!(matches!(*tpl.right, Tpl::As(_)));
+            }
+            Err(e) => panic!("Unexpected error: {e:?}"),
+        }
+    }
+
+    #[test]
+    fn ok_guards() {
+        let input = r#"
+            case m of
+            | Just (x) if x > 0 -> x;
+            | _ -> 0;
+        "#;
+
+        match parse(input) {
+            Ok(ast) => {
+                let Term::Case(term) = &ast.body else {
+                    panic!("Unexpected term: {:?}", ast.body);
+                };
+                assert!(matches!(term.branches[0].guard, Some(Term::Opr(_))));
+                assert!(term.branches[1].guard.is_none());
+            }
+            Err(e) => panic!("Unexpected error: {e:?}"),
+        }
+    }
+
+    #[test]
+    fn ok_lambda_patterns() {
+        match parse(r#"\(a, b) _ (Box x) -> a"#) {
+            Ok(ast) => {
+                let Term::Abs(term) = &ast.body else {
+                    panic!("Unexpected term: {:?}", ast.body);
+                };
+                assert!(matches!(term.arg, Tpl::Pair(_)));
+                let Term::Abs(term) = &*term.body else {
+                    panic!("Unexpected term: {:?}", term.body);
+                };
+                assert!(matches!(term.arg, Tpl::Wild(_)));
+                let Term::Abs(term) = &*term.body else {
+                    panic!("Unexpected term: {:?}", term.body);
+                };
+                assert!(matches!(term.arg, Tpl::Con(_)));
+            }
+            Err(e) => panic!("Unexpected error: {e:?}"),
+        }
+    }
+
+    #[test]
+    fn ok_function_definitions() {
+        match parse("letrec f x (a, b) = f x (a, b); in f") {
+            Ok(ast) => {
+                let Term::Letrec(term) = &ast.body else {
+                    panic!("Unexpected term: {:?}", ast.body);
+                };
+                assert!(matches!(&term.tpl, Tpl::Var(name) if name.val == "f"));
+                let Term::Abs(abs) = &*term.term else {
+                    panic!("Unexpected term: {:?}", term.term);
+                };
+                assert_eq!(7, abs.loc.begin());
+                assert!(matches!(abs.arg, Tpl::Var(_)));
+                assert!(matches!(&*abs.body, Term::Abs(abs) if matches!(abs.arg, Tpl::Pair(_))));
+            }
+            Err(e) => panic!("Unexpected error: {e:?}"),
+        }
+    }
+
+    #[test]
+    fn ok_top_level_defs() {
+        match parse("infixl 1 |> = apply; apply x f = f x; one = 1;") {
+            Ok(ast) => {
+                assert!(matches!(&ast.defs[0], Def::OprDef(_)));
+                let Def::ValDef(def) = &ast.defs[1] else {
+                    panic!("Unexpected def: {:?}", ast.defs[1]);
+                };
+                assert!(matches!(def.term, Term::Abs(_)));
+                assert!(matches!(&ast.defs[2], Def::ValDef(def) if def.name.val == "one"));
+                assert!(matches!(&ast.body, Term::Var(name) if name.val == "main"));
+            }
+            Err(e) => panic!("Unexpected error: {e:?}"),
+        }
+    }
+
+    #[test]
+    fn ok_do_blocks() {
+        match parse(r#"do { print "a"; print "b"; x }"#) {
+            Ok(ast) => {
+                let Term::Seq(term) = &ast.body else {
+                    panic!("Unexpected term: {:?}", ast.body);
+                };
+                assert_eq!((5, 9), (term.first.loc().begin(), term.first.loc().len()));
+                let Term::Seq(term) = &*term.second else {
+                    panic!("Unexpected term: {:?}", term.second);
+                };
+                assert!(matches!(&*term.second, Term::Var(name) if name.val == "x"));
+            }
+            Err(e) => panic!("Unexpected error: {e:?}"),
+        }
+
+        assert!(parse("do { }").is_err());
+        assert!(parse("do { a; }").is_err());
+    }
+
+    #[test]
+    fn err_do_block_opens_comment() {
+        assert!(matches!(
+            &parse("do { -1; 2 }").map(|ast| ast.body),
+            Ok(Term::Seq(_))
+        ));
+
+        match parse("do {-1; 2}") {
+            Err(ParseErr::UnterminatedComment(span)) => assert_eq!(3, span.offset()),
+            res => panic!("Expected unterminated comment, got {res:?}"),
+        }
+    }
+
+    #[test]
+    fn ok_where() {
+        let input = r#"
+            f a where { a = 1; b = a + 1; f x = g x + b; g x = f x; }
+        "#;
+
+        match parse(input) {
+            Ok(ast) => {
+                let Term::Let(term) = &ast.body else {
+                    panic!("Unexpected term: {:?}", ast.body);
+                };
+                assert!(matches!(&term.tpl, Tpl::Var(name) if name.val == "a"));
+                let Term::Let(term) = &*term.body else {
+                    panic!("Unexpected term: {:?}", term.body);
+                };
+                assert!(matches!(&term.tpl, Tpl::Var(name) if name.val == "b"));
+                let Term::Letrec(term) = &*term.body else {
+                    panic!("Unexpected term: {:?}", term.body);
+                };
+                let Tpl::Pair(tpl) = &term.tpl else {
+                    panic!("Unexpected tpl: {:?}", term.tpl);
+                };
+                assert!(matches!(&*tpl.left, Tpl::Var(name) if name.loc.begin() == 43));
+                assert!(matches!(&*term.body, Term::App(_)));
+            }
+            Err(e) => panic!("Unexpected error: {e:?}"),
+        }
+
+        assert!(parse("x where { }").is_err());
+    }
+
+    #[test]
+    fn ok_sections() {
+        let cases = [
+            ("(+)", false, false),
+            ("(+ 1)", false, true),
+            ("(f x -)", true, false),
+            ("(++ f x)", false, true),
+            ("(|> \\x -> x)", false, true),
+        ];
+
+        for (input, has_left, has_right) in cases {
+            match parse(input) {
+                Ok(ast) => {
+                    let Term::Section(term) = &ast.body else {
+                        panic!("Unexpected term for {input}: {:?}", ast.body);
+                    };
+                    assert_eq!(has_left, term.left.is_some(), "{input}");
+                    assert_eq!(has_right, term.right.is_some(), "{input}");
+                    assert_eq!(input.len(), term.loc.len());
+                }
+                Err(e) => panic!("Unexpected error for {input}: {e:?}"),
+            }
+        }
+
+        assert!(parse("(a + b -)").is_err());
+        assert!(parse("(+ a * b)").is_err());
+    }
+
+    #[test]
+    fn ok_negation() {
+        let opr = |input: &str, opr: &str| match parse(input) {
+            Ok(ast) => assert!(
+                matches!(&ast.body, Term::Opr(term) if term.name.val == opr),
+                "Unexpected term for {input}: {:?}",
+                ast.body
+            ),
+            Err(e) => panic!("Unexpected error for {input}: {e:?}"),
+        };
+        opr("n-1", "-");
+        opr("f -1", "-");
+        opr("-n * 2", "*");
+
+        match parse("-f x") {
+            Ok(ast) => {
+                let Term::Neg(term) = &ast.body else {
+                    panic!("Unexpected term: {:?}", ast.body);
+                };
+                assert!(matches!(*term.term, Term::App(_)));
+            }
+            Err(e) => panic!("Unexpected error: {e:?}"),
+        }
+
+        match parse(" - 9223372036854775808") {
+            Ok(ast) => {
+                let Term::Lit(Lit::Int(lit)) = &ast.body else {
+                    panic!("Unexpected term: {:?}", ast.body);
+                };
+                assert_eq!(i64::MIN, lit.val);
+                assert_eq!((1, 21), (lit.loc.begin(), lit.loc.len()));
+            }
+            Err(e) => panic!("Unexpected error: {e:?}"),
+        }
+    }
+
+    #[test]
+    fn ok_numeric_literals() {
+        let ints = [
+            ("0xFF", 255),
+            ("0o17", 15),
+            ("0B1010", 10),
+            ("1_000_000", 1_000_000),
+            ("-0x8000_0000_0000_0000", i64::MIN),
+        ];
+        for (input, val) in ints {
+            match parse(input) {
+                Ok(ast) => assert!(
+                    matches!(&ast.body, Term::Lit(Lit::Int(lit)) if lit.val == val),
+                    "Unexpected term for {input}: {:?}",
+                    ast.body
+                ),
+                Err(e) => panic!("Unexpected error for {input}: {e:?}"),
+            }
+        }
+
+        let doubles = [
+            ("1e-9", 1e-9),
+            ("6.02E23", 6.02e23),
+            ("1.", 1.0),
+            ("-2.5e1", -25.0),
+        ];
+        for (input, val) in doubles {
+            match parse(input) {
+                Ok(ast) => assert!(
+                    matches!(&ast.body, Term::Lit(Lit::Double(lit)) if lit.val == val),
+                    "Unexpected term for {input}: {:?}",
+                    ast.body
+                ),
+                Err(e) => panic!("Unexpected error for {input}: {e:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn err_bad_int_literals() {
+        let inputs = [
+            ("x + 9223372036854775808", 4, 19),
+            ("x + 0xFFFF_FFFF_FFFF_FFFF", 4, 21),
+            ("f 0b102", 2, 5),
+            ("f 0x", 2, 2),
+            ("f 1_", 2, 2),
+            ("f 1__0", 2, 4),
+            ("f 0x_FF", 2, 5),
+            ("f -1_", 2, 3),
+        ];
+
+        for (input, begin, len) in inputs {
+            match parse(input) {
+                Err(ParseErr::BadIntLiteral(span, _)) => {
+                    assert_eq!((begin, len), (span.offset(), span.len()), "{input}")
+                }
+                res => panic!("Expected bad int literal for {input}, got {res:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn err_bad_double_literals() {
+        let inputs = [("f 1_.5", 2, 4), ("f 1.5_", 2, 4), ("f 1.0e5_", 2, 6)];
+
+        for (input, begin, len) in inputs {
+            match parse(input) {
+                Err(ParseErr::BadDoubleLiteral(span, _)) => {
+                    assert_eq!((begin, len), (span.offset(), span.len()), "{input}")
+                }
+                res => panic!("Expected bad double literal for {input}, got {res:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn err_negative_literal() {
+        match parse("-9223372036854775809") {
+            Err(ParseErr::BadIntLiteral(span, _)) => {
+                assert_eq!((0, 20), (span.offset(), span.len()))
+            }
+            res => panic!("Expected bad int literal, got {res:?}"),
+        }
+    }
+
+    #[test]
+    fn ok_negative_literal_pattern() {
+        match parse("case -1 of | -1 -> 0; | _ -> 1;") {
+            Ok(ast) => {
+                let Term::Case(term) = &ast.body else {
+                    panic!("Unexpected term: {:?}", ast.body);
+                };
+                let Tpl::Lit(Lit::Int(lit)) = &term.branches[0].tpl else {
+                    panic!("Unexpected tpl: {:?}", term.branches[0].tpl);
+                };
+                assert_eq!(-1, lit.val);
+                assert_eq!((13, 2), (lit.loc.begin(), lit.loc.len()));
+            }
+            Err(e) => panic!("Unexpected error: {e:?}"),
+        }
+    }
+
+    #[test]
+    fn err_double_literal_pattern() {
+        let inputs = [
+            ("case -1.5 of | -1.5 -> 0; | _ -> 1;", 16),
+            ("case 1.5 of | 1.5 -> 0; | _ -> 1;", 14),
+        ];
+
+        for (input, begin) in inputs {
+            match parse(input) {
+                Err(ParseErr::UnexpectedToken(span, _, TokenKind::Double)) => {
+                    assert_eq!(begin, span.offset(), "{input}")
+                }
+                res => panic!("Expected unexpected token for {input}, got {res:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn ok_where_scopes_over_whole_body() {
+        let inputs = [
+            "f x = if x > 0 then g x else h x where { g y = y; h y = y; };",
+            "f x = case x of | 0 -> g x; | _ -> h x; where { g y = y; h y = y; };",
+            "f x = g x + h x where { g y = y; h y = y; };",
+            "main = let f x = g x * 2 where { g y = y; }; in f 1;",
+        ];
+
+        for input in inputs {
+            match parse(input) {
+                Ok(ast) => {
+                    let Def::ValDef(def) = &ast.defs[0] else {
+                        panic!("Unexpected def for {input}: {:?}", ast.defs[0]);
+                    };
+                    let body = match &def.term {
+                        Term::Abs(term) => &*term.body,
+                        Term::Let(term) => match &term.tpl {
+                            Tpl::Var(name) if name.val == "f" => match &*term.term {
+                                Term::Abs(term) => &*term.body,
+                                term => panic!("Unexpected term for {input}: {term:?}"),
+                            },
+                            tpl => panic!("Unexpected tpl for {input}: {tpl:?}"),
+                        },
+                        term => panic!("Unexpected term for {input}: {term:?}"),
+                    };
+                    // the helpers wrap the whole body of `f`
+                    assert!(matches!(body, Term::Let(_)), "{input}: {body:?}");
+                }
+                Err(e) => panic!("Unexpected error for {input}: {e:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn err_chained_comparison() {
+        match parse("a < b < c") {
+            Ok(res) => panic!("This test should fail, but got {res:?}"),
+            Err(e) => assert_eq!(6, e.begin()),
+        }
+    }
+
+    #[test]
+    fn err_mixed_assoc() {
+        let input = r#"
+            infixr 6 <+> = \a b -> a + b;
+            a + b <+> c
+        "#;
+
+        if let Ok(res) = parse(input) {
+            panic!("This test should fail, but got {res:?}")
+        }
+    }
+
+    #[test]
+    fn err_inside_definitions() {
+        let inputs = [
+            ("f x = x + ; main = f 1;", 10),
+            ("main = f 1; f x = if x then 1;", 29),
+            ("infixl 6 <+> = \\a b -> ; main = 1;", 23),
+        ];
+
+        for (input, offset) in inputs {
+            match parse(input) {
+                Ok(res) => panic!("This test should fail for {input}, but got {res:?}"),
+                Err(e) => assert_eq!(offset, e.begin(), "for {input}: {e:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn err_bad_precedence() {
+        let input = "infixl 10 <+> = \\a b -> a; 1 <+> 2";
+
+        match parse(input) {
+            Ok(res) => panic!("This test should fail, but got {res:?}"),
+            Err(e) => assert_eq!(7, e.begin()),
+        }
+    }
+
     #[test]
     fn err_invali_ast() {
         let input = "data Maybe = Just Int |";
@@ -20,6 +20,8 @@ pub enum TokenKind {
     Int,
     Double,
     Str,
+    True,
+    False,
     Let,
     Letrec,
     Data,
@@ -32,12 +34,16 @@ pub enum TokenKind {
     Infixl,
     Infixr,
     As,
+    Do,
+    Where,
     Udent,
     Ident,
+    Wild,
     Assign,
     Backslash,
     Pipe,
     Arrow,
+    Colon,
     Opr,
 }
 
@@ -1,20 +1,55 @@
-use crate::err::{VerifyErr, VerifyRes};
+use crate::err::{VerifyErr, VerifyRes, VerifyWarn};
 use gokart_core::{
-    Counter, FreeIdx, Loc, LocExt, Name, Predef, Subst, Tag, Tpl, Type, TypeIdx, VarName,
+    Counter, DoubleNegs, FreeIdx, Lit, Loc, LocExt, Name, Predef, Subst, Tag, Tpl, Type, TypeExpr,
+    TypeIdx, VarName,
 };
 use std::collections::{HashMap, HashSet};
 
+// a type with universally quantified free types
+#[derive(Debug, Clone)]
+pub struct Scheme {
+    pub fts: Vec<FreeIdx>,
+    pub ty: Type,
+}
+
+impl Scheme {
+    #[inline]
+    pub fn mono(ty: Type) -> Self {
+        Self {
+            fts: Vec::new(),
+            ty,
+        }
+    }
+}
+
+// a free type is either still unknown, remembering the let-nesting level
+// it was created at, or bound to some other type. A rigid one comes from an
+// annotation and stands for any type, so it can't be bound while in scope
+#[derive(Debug, Clone)]
+enum Slot<'a> {
+    Unbound(usize),
+    Rigid(usize, Loc<'a>),
+    Bound(Type),
+}
+
 #[derive(Debug)]
 pub struct Ctx<'a> {
     ty_cnt: Counter,
-    ft_cnt: Counter,
     tag_cnt: Counter,
-    vars: HashMap<VarName<'a>, Type>,
-    ctors: HashMap<VarName<'a>, (Type, TypeIdx)>,
+    fts: Vec<Slot<'a>>,
+    level: usize,
+    rigid: Vec<FreeIdx>,
+    scopes: Vec<usize>,
+    vars: HashMap<VarName<'a>, Scheme>,
+    ctors: HashMap<VarName<'a>, (Scheme, TypeIdx)>,
     tags: HashMap<VarName<'a>, Tag>,
     cons: HashMap<TypeIdx, HashSet<Tag>>,
     tys: HashMap<VarName<'a>, TypeIdx>,
+    arities: HashMap<TypeIdx, usize>,
     oprs: HashMap<&'static str, (Type, Type, Type)>,
+    // the types are known only after the whole program is checked
+    discarded: Vec<(Loc<'a>, Type)>,
+    negs: Vec<(Loc<'a>, Loc<'a>, Type)>,
 }
 
 impl<'a> Ctx<'a> {
@@ -25,7 +60,7 @@ impl<'a> Ctx<'a> {
         let vars = HashMap::from_iter(
             Predef::funcs(&tys)
                 .into_iter()
-                .map(|func| (func.name, func.ty)),
+                .map(|func| (func.name, Scheme::mono(func.ty))),
         );
         let oprs = HashMap::from_iter(
             Predef::oprs(&tys)
@@ -35,26 +70,102 @@ impl<'a> Ctx<'a> {
 
         Self {
             ty_cnt,
-            ft_cnt: Counter::default(),
             tag_cnt: Counter::default(),
+            fts: Vec::new(),
+            level: 0,
+            rigid: Vec::new(),
+            scopes: Vec::new(),
             vars,
             ctors: HashMap::new(),
             tags: HashMap::new(),
             cons: HashMap::new(),
             tys,
+            arities: HashMap::new(),
             oprs,
+            discarded: Vec::new(),
+            negs: Vec::new(),
         }
     }
 
+    #[inline]
+    pub fn discard(&mut self, loc: Loc<'a>, ty: Type) {
+        self.discarded.push((loc, ty));
+    }
+
+    #[inline]
+    pub fn neg(&mut self, loc: Loc<'a>, term_loc: Loc<'a>, ty: Type) {
+        self.negs.push((loc, term_loc, ty));
+    }
+
+    // an operand of a negation still unknown at the end is taken for an integer
+    pub fn double_negs(&mut self) -> VerifyRes<DoubleNegs> {
+        let mut res = DoubleNegs::new();
+        for (loc, term_loc, ty) in self.negs.clone() {
+            match (self.find(&ty), self.double_ty()) {
+                (Type::Prim(idx), Type::Prim(double)) if idx == double => {
+                    res.insert(loc.begin());
+                }
+                (ty, _) => self.resolve(&self.int_ty(), &ty, &term_loc)?,
+            }
+        }
+        Ok(res)
+    }
+
+    // values thrown away by sequencing must be `()`
+    pub fn warns(&self) -> Vec<VerifyWarn> {
+        self.discarded
+            .iter()
+            .filter_map(|(loc, ty)| match self.expand(ty) {
+                Type::Prim(idx) if idx == self.unit_idx() => None,
+                Type::Free(_) => None,
+                ty => Some(VerifyWarn::DiscardedValue(loc.into_span(), ty.show(self))),
+            })
+            .collect()
+    }
+
     #[inline]
     pub fn next_ft(&mut self) -> Type {
-        Type::Free(self.ft_cnt.step())
+        self.fts.push(Slot::Unbound(self.level));
+        Type::Free(self.fts.len() - 1)
+    }
+
+    #[inline]
+    fn next_rigid(&mut self, loc: Loc<'a>) -> Type {
+        self.fts.push(Slot::Rigid(self.level, loc));
+        self.rigid.push(self.fts.len() - 1);
+        Type::Free(self.fts.len() - 1)
+    }
+
+    #[inline]
+    pub fn enter_level(&mut self) {
+        self.level += 1;
+    }
+
+    #[inline]
+    pub fn leave_level(&mut self) {
+        self.level -= 1;
+    }
+
+    #[inline]
+    pub fn enter_scope(&mut self) {
+        self.scopes.push(self.rigid.len());
+    }
+
+    // the annotation type variables become ordinary free types
+    // once the annotated binding goes out of scope
+    pub fn leave_scope(&mut self) {
+        let start = self.scopes.pop().expect("Scopes are balanced");
+        for idx in self.rigid.split_off(start) {
+            if let Slot::Rigid(level, _) = self.fts[idx] {
+                self.fts[idx] = Slot::Unbound(level);
+            }
+        }
     }
 
     #[inline]
-    pub fn var(&self, name: &Name<'a>) -> VerifyRes<&Type> {
-        match self.vars.get(name.val) {
-            Some(ty) => Ok(ty),
+    pub fn var(&mut self, name: &Name<'a>) -> VerifyRes<Type> {
+        match self.vars.get(name.val).cloned() {
+            Some(scheme) => Ok(self.instantiate(&scheme)),
             None => Err(VerifyErr::UnknownName(
                 name.loc.into_span(),
                 name.val.to_string(),
@@ -63,49 +174,108 @@ impl<'a> Ctx<'a> {
     }
 
     #[inline]
-    pub fn push_var(&mut self, name: VarName<'a>, ty: Type) -> Option<Type> {
-        self.vars.insert(name, ty)
+    pub fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        let subst = scheme
+            .fts
+            .iter()
+            .map(|ft| (*ft, self.next_ft()))
+            .collect::<Subst>();
+        scheme.ty.apply(&subst)
+    }
+
+    // quantifies the free types created in the levels that were already left
+    pub fn generalize(&self, ty: &Type) -> Scheme {
+        let ty = self.expand(ty);
+        let mut fts = HashSet::new();
+        ty.collect_fts(&mut fts);
+
+        // an operand of a negation has to be either an integer or a double, not both
+        let mut negs = HashSet::new();
+        for (_, _, ty) in self.negs.iter() {
+            self.expand(ty).collect_fts(&mut negs);
+        }
+
+        let mut fts = fts
+            .into_iter()
+            .filter(|ft| matches!(self.fts[*ft], Slot::Unbound(level) if level > self.level))
+            .filter(|ft| !negs.contains(ft))
+            .collect::<Vec<_>>();
+        fts.sort();
+
+        Scheme { fts, ty }
+    }
+
+    #[inline]
+    pub fn push_var(&mut self, name: VarName<'a>, ty: Type) -> Option<Scheme> {
+        self.push_scheme(name, Scheme::mono(ty))
     }
 
     #[inline]
-    pub fn pop_var(&mut self, name: VarName<'a>, prev_ty: Option<Type>) {
-        match prev_ty {
-            Some(prev_ty) => self.push_var(name, prev_ty),
-            None => None,
+    pub fn push_scheme(&mut self, name: VarName<'a>, scheme: Scheme) -> Option<Scheme> {
+        self.vars.insert(name, scheme)
+    }
+
+    #[inline]
+    pub fn pop_var(&mut self, name: VarName<'a>, prev: Option<Scheme>) {
+        match prev {
+            Some(prev) => self.vars.insert(name, prev),
+            None => self.vars.remove(name),
         };
     }
 
     #[inline]
-    pub fn push_vars(&mut self, names: &[VarName<'a>], tys: Vec<Type>) -> Vec<Option<Type>> {
+    pub fn push_vars(&mut self, names: &[VarName<'a>], tys: Vec<Type>) -> Vec<Option<Scheme>> {
+        self.push_schemes(names, tys.into_iter().map(Scheme::mono).collect())
+    }
+
+    #[inline]
+    pub fn push_schemes(
+        &mut self,
+        names: &[VarName<'a>],
+        schemes: Vec<Scheme>,
+    ) -> Vec<Option<Scheme>> {
         names
             .iter()
-            .zip(tys)
-            .map(|(name, ty)| self.push_var(name, ty))
+            .zip(schemes)
+            .map(|(name, scheme)| self.push_scheme(name, scheme))
             .collect()
     }
 
     #[inline]
-    pub fn pop_vars(&mut self, names: &[VarName<'a>], prev_tys: Vec<Option<Type>>) {
+    pub fn pop_vars(&mut self, names: &[VarName<'a>], prevs: Vec<Option<Scheme>>) {
         names
             .iter()
-            .zip(prev_tys)
-            .for_each(|(name, prev_ty)| self.pop_var(name, prev_ty));
+            .zip(prevs)
+            .for_each(|(name, prev)| self.pop_var(name, prev));
     }
 
+    // the type of a variable bound by a lambda, a pattern or a recursive definition
     #[inline]
-    pub fn ctor(&self, name: &Name<'a>) -> VerifyRes<(Type, TypeIdx)> {
-        match self.ctors.get(name.val) {
-            Some(x) => Ok(x.clone()),
-            None => Err(VerifyErr::UnknownCtor(
-                name.loc.into_span(),
-                name.val.to_string(),
-            )),
+    pub fn mono(&self, name: VarName<'a>) -> Type {
+        self.vars.get(name).unwrap().ty.clone()
+    }
+
+    // the argument and the result types of a fresh instance of the constructor
+    #[inline]
+    pub fn ctor(&mut self, name: &Name<'a>) -> VerifyRes<(Type, Type, TypeIdx)> {
+        let (scheme, idx) = match self.ctors.get(name.val) {
+            Some(x) => x.clone(),
+            None => {
+                return Err(VerifyErr::UnknownCtor(
+                    name.loc.into_span(),
+                    name.val.to_string(),
+                ))
+            }
+        };
+        match self.instantiate(&scheme) {
+            Type::Func(from, into) => Ok((*from, *into, idx)),
+            _ => unreachable!("Constructors always have function types"),
         }
     }
 
     #[inline]
-    pub fn add_ctor(&mut self, name: &Name<'a>, from: Type, into: TypeIdx) -> VerifyRes<()> {
-        match self.ctors.insert(name.val, (from, into)) {
+    pub fn add_ctor(&mut self, name: &Name<'a>, scheme: Scheme, into: TypeIdx) -> VerifyRes<()> {
+        match self.ctors.insert(name.val, (scheme, into)) {
             Some(_) => Err(VerifyErr::CtorRedefinition(
                 name.loc.into_span(),
                 name.val.to_string(),
@@ -125,6 +295,38 @@ impl<'a> Ctx<'a> {
         }
     }
 
+    #[inline]
+    pub fn ctor_idx(&self, name: &Name<'a>) -> VerifyRes<TypeIdx> {
+        match self.ctors.get(name.val) {
+            Some((_, idx)) => Ok(*idx),
+            None => Err(VerifyErr::UnknownCtor(
+                name.loc.into_span(),
+                name.val.to_string(),
+            )),
+        }
+    }
+
+    // whether the constructor holds just `()`
+    #[inline]
+    pub fn is_nullary(&self, name: VarName<'a>) -> bool {
+        match self.ctors.get(name) {
+            Some((scheme, _)) => {
+                matches!(&scheme.ty, Type::Func(from, _) if matches!(**from, Type::Prim(idx) if idx == self.unit_idx()))
+            }
+            None => false,
+        }
+    }
+
+    #[inline]
+    pub fn tag_name(&self, tag: Tag) -> VarName<'a> {
+        for (name, t) in self.tags.iter() {
+            if *t == tag {
+                return name;
+            }
+        }
+        unreachable!("It's guaranteed that a constructor with such tag exists")
+    }
+
     #[inline]
     pub fn tag(&self, name: &Name<'a>) -> VerifyRes<Tag> {
         match self.tags.get(name.val) {
@@ -152,9 +354,85 @@ impl<'a> Ctx<'a> {
         }
     }
 
+    pub fn type_expr(
+        &self,
+        expr: &TypeExpr<'a>,
+        params: &HashMap<VarName<'a>, Type>,
+    ) -> VerifyRes<Type> {
+        match expr {
+            TypeExpr::Empty(_) => Ok(self.unit_ty()),
+            TypeExpr::Pair(expr) => Ok(Type::pair(
+                self.type_expr(&expr.left, params)?,
+                self.type_expr(&expr.right, params)?,
+            )),
+            TypeExpr::Func(expr) => Ok(Type::func(
+                self.type_expr(&expr.from, params)?,
+                self.type_expr(&expr.into, params)?,
+            )),
+            TypeExpr::Var(name) => match params.get(name.val) {
+                Some(ty) => Ok(ty.clone()),
+                None => Err(VerifyErr::UnknownTypeVar(
+                    name.loc.into_span(),
+                    name.val.to_string(),
+                )),
+            },
+            TypeExpr::Con(expr) => {
+                let idx = self.ty(&expr.name)?;
+                let arity = self.arity(idx);
+                if arity != expr.args.len() {
+                    return Err(VerifyErr::KindMismatch(
+                        expr.loc.into_span(),
+                        expr.name.val.to_string(),
+                        arity,
+                        expr.args.len(),
+                    ));
+                }
+
+                let args = expr
+                    .args
+                    .iter()
+                    .map(|arg| self.type_expr(arg, params))
+                    .collect::<VerifyRes<Vec<_>>>()?;
+                Ok(Type::app(idx, args))
+            }
+        }
+    }
+
+    // type variables of an annotation stand for any types, see `leave_scope`
+    pub fn ann_ty(&mut self, expr: &TypeExpr<'a>) -> VerifyRes<Type> {
+        fn go<'b>(
+            expr: &TypeExpr<'b>,
+            ctx: &mut Ctx<'b>,
+            params: &mut HashMap<VarName<'b>, Type>,
+            loc: Loc<'b>,
+        ) {
+            match expr {
+                TypeExpr::Empty(_) => (),
+                TypeExpr::Var(name) => {
+                    if !params.contains_key(name.val) {
+                        params.insert(name.val, ctx.next_rigid(loc));
+                    }
+                }
+                TypeExpr::Con(expr) => expr.args.iter().for_each(|arg| go(arg, ctx, params, loc)),
+                TypeExpr::Pair(expr) => {
+                    go(&expr.left, ctx, params, loc);
+                    go(&expr.right, ctx, params, loc);
+                }
+                TypeExpr::Func(expr) => {
+                    go(&expr.from, ctx, params, loc);
+                    go(&expr.into, ctx, params, loc);
+                }
+            }
+        }
+
+        let mut params = HashMap::new();
+        go(expr, self, &mut params, expr.loc());
+        self.type_expr(expr, &params)
+    }
+
     #[inline]
-    pub fn prim_ty(&self, name: &Name<'a>) -> VerifyRes<Type> {
-        self.ty(name).map(Type::Prim)
+    pub fn arity(&self, idx: TypeIdx) -> usize {
+        self.arities.get(&idx).copied().unwrap_or(0)
     }
 
     #[inline]
@@ -182,6 +460,21 @@ impl<'a> Ctx<'a> {
         Type::Prim(*self.tys.get("Str").unwrap())
     }
 
+    #[inline]
+    pub fn bool_ty(&self) -> Type {
+        Type::Prim(*self.tys.get("Bool").unwrap())
+    }
+
+    #[inline]
+    pub fn lit_ty(&self, lit: &Lit) -> Type {
+        match lit {
+            Lit::Int(_) => self.int_ty(),
+            Lit::Double(_) => self.double_ty(),
+            Lit::Str(_) => self.str_ty(),
+            Lit::Bool(_) => self.bool_ty(),
+        }
+    }
+
     #[inline]
     pub fn get_prim(&self, idx: TypeIdx) -> VarName<'a> {
         for (name, i) in self.tys.iter() {
@@ -193,88 +486,213 @@ impl<'a> Ctx<'a> {
     }
 
     #[inline]
-    pub fn add_ty(&mut self, name: &Name<'a>) -> VerifyRes<TypeIdx> {
+    pub fn add_ty(&mut self, name: &Name<'a>, arity: usize) -> VerifyRes<TypeIdx> {
         let idx = self.ty_cnt.step();
         match self.tys.insert(name.val, idx) {
             Some(_) => Err(VerifyErr::TypeRedefinition(
                 name.loc.into_span(),
                 name.val.to_string(),
             )),
-            None => Ok(idx),
+            None => {
+                self.arities.insert(idx, arity);
+                Ok(idx)
+            }
         }
     }
 
-    pub fn opr(&self, name: &Name<'a>) -> VerifyRes<(Type, Type, Type)> {
-        match self.oprs.get(name.val) {
-            Some(x) => Ok(x.clone()),
-            None => Err(VerifyErr::UnknownOpr(
+    pub fn opr(&mut self, name: &Name<'a>) -> VerifyRes<(Type, Type, Type)> {
+        if let Some(x) = self.oprs.get(name.val) {
+            return Ok(x.clone());
+        }
+
+        // user-defined operations live among the variables
+        if !self.vars.contains_key(name.val) {
+            return Err(VerifyErr::UnknownOpr(
                 name.loc.into_span(),
                 name.val.to_string(),
-            )),
+            ));
         }
+        let ty = self.var(name)?;
+        let (left, right, res) = (self.next_ft(), self.next_ft(), self.next_ft());
+        let expected = Type::func(left.clone(), Type::func(right.clone(), res.clone()));
+        self.resolve(&expected, &ty, &name.loc)?;
+
+        Ok((left, right, res))
     }
 
+    // user-defined operations can't shadow the predefined ones
     #[inline]
-    pub fn apply(&mut self, subst: &Subst) {
-        for ty in self.vars.values_mut() {
-            *ty = ty.apply(subst);
+    pub fn check_opr(&self, name: &Name<'a>) -> VerifyRes<()> {
+        match self.oprs.contains_key(name.val) || self.vars.contains_key(name.val) {
+            true => Err(VerifyErr::OprRedefinition(
+                name.loc.into_span(),
+                name.val.to_string(),
+            )),
+            false => Ok(()),
         }
     }
 
-    #[inline]
-    pub fn resolve(&mut self, left: &Type, right: &Type, loc: &Loc<'a>) -> VerifyRes<Subst> {
-        match resolve(left, right) {
-            Ok(subst) => Ok(subst),
-            Err(_) => Err(VerifyErr::TypeMismatch(
-                loc.into_span(),
-                left.show(self),
-                right.show(self),
-            )),
+    // follows the bindings of free types all the way down
+    pub fn expand(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Prim(idx) => Type::Prim(*idx),
+            Type::App(idx, args) => Type::App(*idx, args.iter().map(|a| self.expand(a)).collect()),
+            Type::Pair(a, b) => Type::pair(self.expand(a), self.expand(b)),
+            Type::Func(a, b) => Type::func(self.expand(a), self.expand(b)),
+            Type::Free(idx) => match &self.fts[*idx] {
+                Slot::Bound(ty) => self.expand(ty),
+                Slot::Unbound(_) | Slot::Rigid(..) => Type::Free(*idx),
+            },
         }
     }
 
-    #[inline]
-    pub fn resolve_apply(&mut self, left: &Type, right: &Type, loc: &Loc<'a>) -> VerifyRes<Subst> {
-        let subst = self.resolve(left, right, loc)?;
-        self.apply(&subst);
-        Ok(subst)
+    // finds the representative of a type, compressing the path to it
+    pub fn find(&mut self, ty: &Type) -> Type {
+        match ty {
+            Type::Free(idx) => match self.fts[*idx].clone() {
+                Slot::Bound(bound) => {
+                    let root = self.find(&bound);
+                    self.fts[*idx] = Slot::Bound(root.clone());
+                    root
+                }
+                Slot::Unbound(_) | Slot::Rigid(..) => Type::Free(*idx),
+            },
+            ty => ty.clone(),
+        }
     }
-}
 
-type ResolveRes<T> = Result<T, ()>;
+    pub fn resolve(&mut self, left: &Type, right: &Type, loc: &Loc<'a>) -> VerifyRes<()> {
+        match self.unify(left, right) {
+            Ok(()) => Ok(()),
+            Err(ResolveErr::Infinite(idx, ty)) => {
+                let mut names = Names::default();
+                let var = Type::Free(idx).show_with(self, &mut names);
+                let ty = ty.show_with(self, &mut names);
+                Err(VerifyErr::InfiniteType(
+                    loc.into_span(),
+                    format!("{var} would have to be equal to {ty}"),
+                ))
+            }
+            Err(ResolveErr::Mismatch(left_part, right_part)) => {
+                let mut names = Names::default();
+                let expected = left.show_with(self, &mut names);
+                let found = right.show_with(self, &mut names);
+                let expected_part = left_part.show_with(self, &mut names);
+                let found_part = right_part.show_with(self, &mut names);
+
+                // the clash is deep inside the types
+                let help = (expected_part != expected || found_part != found)
+                    .then(|| format!("{expected_part} is not compatible with {found_part}"));
+                // the annotation is what promised a more general type
+                let loc = [left_part, right_part]
+                    .iter()
+                    .find_map(|part| match part {
+                        Type::Free(idx) => match self.fts[*idx] {
+                            Slot::Rigid(_, loc) => Some(loc),
+                            _ => None,
+                        },
+                        _ => None,
+                    })
+                    .unwrap_or(*loc);
+                Err(VerifyErr::TypeMismatch(
+                    loc.into_span(),
+                    expected,
+                    found,
+                    help,
+                ))
+            }
+        }
+    }
 
-fn resolve(left: &Type, right: &Type) -> ResolveRes<Subst> {
-    fn go(subst: &mut Subst, left: &Type, right: &Type) -> ResolveRes<()> {
-        match (left, right) {
-            (Type::Prim(a), Type::Prim(b)) if a == b => Ok(()),
-            (Type::Pair(a, b), Type::Pair(c, d)) => {
-                go(subst, a, c)?;
-                go(subst, b, d)
+    fn unify(&mut self, left: &Type, right: &Type) -> ResolveRes<()> {
+        match (self.find(left), self.find(right)) {
+            (Type::Free(a), Type::Free(b)) if a == b => Ok(()),
+            (Type::Free(idx), ty) | (ty, Type::Free(idx))
+                if matches!(self.fts[idx], Slot::Unbound(_)) =>
+            {
+                self.bind(idx, ty)
             }
-            (Type::Func(a, b), Type::Func(c, d)) => {
-                go(subst, a, c)?;
-                go(subst, b, d)
+            (Type::Prim(a), Type::Prim(b)) if a == b => Ok(()),
+            (Type::App(a, xs), Type::App(b, ys)) if a == b && xs.len() == ys.len() => xs
+                .iter()
+                .zip(ys.iter())
+                .try_for_each(|(x, y)| self.unify(x, y)),
+            (Type::Pair(a, b), Type::Pair(c, d)) | (Type::Func(a, b), Type::Func(c, d)) => {
+                self.unify(&a, &c)?;
+                self.unify(&b, &d)
             }
-            (Type::Free(idx), ty) => {
-                subst.insert(*idx, ty.clone());
-                Ok(())
+            (left, right) => Err(ResolveErr::Mismatch(left, right)),
+        }
+    }
+
+    fn bind(&mut self, idx: FreeIdx, ty: Type) -> ResolveRes<()> {
+        let level = match self.fts[idx] {
+            Slot::Unbound(level) => level,
+            Slot::Rigid(..) => unreachable!("Rigid types are never bound"),
+            Slot::Bound(_) => unreachable!("Only representatives are bound"),
+        };
+        if self.occurs(idx, level, &ty).is_err() {
+            return Err(ResolveErr::Infinite(idx, ty));
+        }
+        self.fts[idx] = Slot::Bound(ty);
+        Ok(())
+    }
+
+    // besides the occurs check, moves the free types of the bound type
+    // to the outermost level, so they are not generalized too early
+    fn occurs(&mut self, idx: FreeIdx, level: usize, ty: &Type) -> Result<(), ()> {
+        match self.find(ty) {
+            Type::Prim(_) => Ok(()),
+            Type::App(_, args) => args.iter().try_for_each(|a| self.occurs(idx, level, a)),
+            Type::Pair(a, b) | Type::Func(a, b) => {
+                self.occurs(idx, level, &a)?;
+                self.occurs(idx, level, &b)
             }
-            (ty, Type::Free(idx)) => {
-                subst.insert(*idx, ty.clone());
+            Type::Free(other) if other == idx => Err(()),
+            Type::Free(other) => {
+                if let Slot::Unbound(other_level) | Slot::Rigid(other_level, _) =
+                    &mut self.fts[other]
+                {
+                    *other_level = level.min(*other_level);
+                }
                 Ok(())
             }
-            _ => Err(()),
         }
     }
+}
+
+// the parts of the types that could not be resolved
+enum ResolveErr {
+    Mismatch(Type, Type),
+    Infinite(FreeIdx, Type),
+}
 
-    let mut subst = Subst::new();
-    go(&mut subst, left, right)?;
-    Ok(subst)
+type ResolveRes<T> = Result<T, ResolveErr>;
+
+// readable names of free types, shared by all the types of one message
+#[derive(Debug, Default)]
+pub struct Names(HashMap<FreeIdx, String>);
+
+impl Names {
+    pub fn name(&mut self, idx: FreeIdx) -> String {
+        let n = self.0.len();
+        self.0
+            .entry(idx)
+            .or_insert_with(|| {
+                let letter = (b'a' + (n % 26) as u8) as char;
+                match n / 26 {
+                    0 => letter.to_string(),
+                    k => format!("{letter}{k}"),
+                }
+            })
+            .clone()
+    }
 }
 
 pub trait TypeExt {
     fn show(&self, ctx: &Ctx) -> String;
-    fn contains_ft(&self, ft: FreeIdx) -> bool;
+    fn show_with(&self, ctx: &Ctx, names: &mut Names) -> String;
+    fn collect_fts(&self, fts: &mut HashSet<FreeIdx>);
     fn split<'a>(
         self,
         tpl: &Tpl<'a>,
@@ -283,21 +701,47 @@ pub trait TypeExt {
 }
 
 impl TypeExt for Type {
+    #[inline]
     fn show(&self, ctx: &Ctx) -> String {
-        match self {
-            Type::Prim(idx) => ctx.get_prim(*idx).to_string(),
-            Type::Pair(a, b) => format!("({}, {})", a.show(ctx), b.show(ctx)),
-            Type::Func(a, b) => format!("{} -> {}", a.show(ctx), b.show(ctx)),
-            Type::Free(idx) => format!("<{idx}>"),
+        self.show_with(ctx, &mut Names::default())
+    }
+
+    fn show_with(&self, ctx: &Ctx, names: &mut Names) -> String {
+        fn go(ty: &Type, ctx: &Ctx, names: &mut Names) -> String {
+            match ty {
+                Type::Prim(idx) => ctx.get_prim(*idx).to_string(),
+                Type::App(idx, args) => {
+                    args.iter()
+                        .fold(ctx.get_prim(*idx).to_string(), |acc, a| match a {
+                            Type::App(..) | Type::Func(..) => {
+                                format!("{acc} ({})", go(a, ctx, names))
+                            }
+                            _ => format!("{acc} {}", go(a, ctx, names)),
+                        })
+                }
+                Type::Pair(a, b) => format!("({}, {})", go(a, ctx, names), go(b, ctx, names)),
+                Type::Func(a, b) => match **a {
+                    Type::Func(..) => format!("({}) -> {}", go(a, ctx, names), go(b, ctx, names)),
+                    _ => format!("{} -> {}", go(a, ctx, names), go(b, ctx, names)),
+                },
+                Type::Free(idx) => names.name(*idx),
+            }
         }
+
+        go(&ctx.expand(self), ctx, names)
     }
 
-    fn contains_ft(&self, ft: FreeIdx) -> bool {
+    fn collect_fts(&self, fts: &mut HashSet<FreeIdx>) {
         match self {
-            Type::Prim(_) => false,
-            Type::Pair(a, b) => a.contains_ft(ft) || b.contains_ft(ft),
-            Type::Func(a, b) => a.contains_ft(ft) || b.contains_ft(ft),
-            Type::Free(idx) => *idx == ft,
+            Type::Prim(_) => (),
+            Type::App(_, args) => args.iter().for_each(|a| a.collect_fts(fts)),
+            Type::Pair(a, b) | Type::Func(a, b) => {
+                a.collect_fts(fts);
+                b.collect_fts(fts);
+            }
+            Type::Free(idx) => {
+                fts.insert(*idx);
+            }
         }
     }
 
@@ -333,8 +777,20 @@ impl TypeExt for Type {
             ty: Type,
             tpl: &Tpl<'b>,
         ) -> VerifyRes<()> {
-            match (ty, tpl) {
+            match (ctx.find(&ty), tpl) {
                 (ty, Tpl::Var(name)) => add_var(vars, tys, name, ty),
+                (_, Tpl::Wild(_)) => Ok(()),
+                (ty, Tpl::Lit(lit)) => ctx.resolve(&ty, &ctx.lit_ty(lit), &lit.loc()),
+                (ty, Tpl::Con(tpl)) => {
+                    let (from_ty, into_ty, _) = ctx.ctor(&tpl.name)?;
+                    ctx.resolve(&ty, &into_ty, &tpl.loc)?;
+                    go(vars, tys, ctx, from_ty, &tpl.body)
+                }
+                (ty, Tpl::Ann(tpl)) => {
+                    let ann_ty = ctx.ann_ty(&tpl.ty)?;
+                    ctx.resolve(&ann_ty, &ty, &tpl.ty.loc())?;
+                    go(vars, tys, ctx, ann_ty, &tpl.tpl)
+                }
                 (Type::Pair(a, b), Tpl::Pair(tpl)) => {
                     go(vars, tys, ctx, *a, &tpl.left)?;
                     go(vars, tys, ctx, *b, &tpl.right)
@@ -346,7 +802,7 @@ impl TypeExt for Type {
                 (Type::Free(idx), Tpl::Pair(tpl)) => {
                     let left_ty = ctx.next_ft();
                     let right_ty = ctx.next_ft();
-                    ctx.resolve_apply(
+                    ctx.resolve(
                         &Type::Free(idx),
                         &Type::pair(left_ty.clone(), right_ty.clone()),
                         &tpl.loc,
@@ -360,6 +816,9 @@ impl TypeExt for Type {
                     "(_, _)".to_string(),
                 )),
                 (Type::Prim(idx), Tpl::Empty(_)) if idx == ctx.unit_idx() => Ok(()),
+                (Type::Free(idx), Tpl::Empty(tpl)) => {
+                    ctx.resolve(&Type::Free(idx), &ctx.unit_ty(), &tpl.loc)
+                }
                 (ty, Tpl::Empty(tpl)) => Err(VerifyErr::PatternNotMatch(
                     tpl.loc.into_span(),
                     ty.show(ctx),
@@ -27,13 +27,46 @@ pub enum VerifyErr {
     #[diagnostic()]
     CtorRedefinition(#[label("here")] Span, String),
 
+    #[error("Operation already defined: {1}")]
+    #[diagnostic()]
+    OprRedefinition(#[label("here")] Span, String),
+
+    #[error("Value already defined: {1}")]
+    #[diagnostic()]
+    ValueRedefinition(#[label("here")] Span, String),
+
+    #[error("Missing entry point")]
+    #[diagnostic(help("define `main` or end the program with an expression"))]
+    MissingMain(#[label("here")] Span),
+
+    #[error("Type {1} has no constructors")]
+    #[diagnostic()]
+    EmptyType(#[label("here")] Span, String),
+
+    #[error("Type variable already defined: {1}")]
+    #[diagnostic()]
+    TypeVarRedefinition(#[label("here")] Span, String),
+
+    #[error("Unknown type variable: {1}")]
+    #[diagnostic()]
+    UnknownTypeVar(#[label("here")] Span, String),
+
+    #[error("Kind mismatch: type {1} expects {2} arguments, found {3}")]
+    #[diagnostic()]
+    KindMismatch(#[label("here")] Span, String, usize, usize),
+
     #[error("Type mismatch: expected {1}, found {2}")]
     #[diagnostic()]
-    TypeMismatch(#[label("here")] Span, String, String),
+    TypeMismatch(
+        #[label("here")] Span,
+        String,
+        String,
+        #[help] Option<String>,
+    ),
 
     #[error("Infinite type detected")]
     #[diagnostic()]
-    InfiniteType(#[label("here")] Span),
+    InfiniteType(#[label("here")] Span, #[help] String),
 
     #[error("Type {1} doesn't match pattern {2}")]
     #[diagnostic()]
@@ -43,17 +76,31 @@ pub enum VerifyErr {
     #[diagnostic()]
     PatternRedefinition(#[label("here")] Span, String),
 
-    #[error("Invalid case: different branches have different types of constructors")]
+    #[error("Invalid case: branch is never reached")]
     #[diagnostic()]
-    InvalidBranchesType(#[label("here")] Span),
+    RedundantBranch(#[label("here")] Span),
 
-    #[error("Invalid case: branch for constructor already defined")]
+    #[error("Invalid case: not all values are covered")]
     #[diagnostic()]
-    BranchRedefinition(#[label("here")] Span),
+    BranchNotCovered(#[label("here")] Span, #[help] String),
 
-    #[error("Invalid case: not all constructors are covered")]
+    #[error("Invalid pattern: not all values are covered")]
     #[diagnostic()]
-    BranchNotCovered(#[label("here")] Span),
+    RefutablePattern(#[label("here")] Span, #[help] String),
+
+    #[error("Invalid letrec: constructors and literals are not allowed in patterns")]
+    #[diagnostic()]
+    LetrecRefutablePattern(#[label("here")] Span),
 }
 
 pub type VerifyRes<T> = Result<T, VerifyErr>;
+
+#[derive(Error, Debug, Diagnostic)]
+pub enum VerifyWarn {
+    #[error("Value of type {1} is discarded")]
+    #[diagnostic(
+        severity(Warning),
+        help("use `let _ = ...; in` to discard it on purpose")
+    )]
+    DiscardedValue(#[label("here")] Span, String),
+}
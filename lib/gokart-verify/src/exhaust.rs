@@ -0,0 +1,184 @@
+use crate::ctx::Ctx;
+use gokart_core::{Int, Lit, Tag, Tpl, TypeIdx};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ctor {
+    Unit,
+    Pair,
+    Tag(TypeIdx, Tag),
+    Int(Int),
+    Str(String),
+}
+
+impl Ctor {
+    #[inline]
+    fn arity(&self) -> usize {
+        match self {
+            Ctor::Unit | Ctor::Int(_) | Ctor::Str(_) => 0,
+            Ctor::Pair => 2,
+            Ctor::Tag(..) => 1,
+        }
+    }
+
+    // all the constructors of the same type, literals have too many of them
+    fn siblings(&self, ctx: &Ctx) -> Option<Vec<Ctor>> {
+        match self {
+            Ctor::Tag(idx, _) => {
+                let mut tags = ctx.ty_cons(*idx).iter().copied().collect::<Vec<_>>();
+                tags.sort();
+                Some(tags.into_iter().map(|tag| Ctor::Tag(*idx, tag)).collect())
+            }
+            Ctor::Int(_) | Ctor::Str(_) => None,
+            ctor => Some(vec![ctor.clone()]),
+        }
+    }
+}
+
+// the set of values a pattern matches
+#[derive(Debug, Clone)]
+pub enum Space {
+    Wild,
+    Ctor(Ctor, Vec<Space>),
+}
+
+impl Space {
+    pub fn from_tpl(tpl: &Tpl, ctx: &Ctx) -> Space {
+        match tpl {
+            Tpl::Empty(_) => Space::Ctor(Ctor::Unit, Vec::new()),
+            Tpl::Var(_) | Tpl::Wild(_) => Space::Wild,
+            Tpl::Pair(tpl) => Space::Ctor(
+                Ctor::Pair,
+                vec![
+                    Space::from_tpl(&tpl.left, ctx),
+                    Space::from_tpl(&tpl.right, ctx),
+                ],
+            ),
+            Tpl::As(tpl) => Space::from_tpl(&tpl.tpl, ctx),
+            Tpl::Con(tpl) => {
+                // the pattern is already verified, so the constructor exists
+                let tag = ctx.tag(&tpl.name).unwrap();
+                let idx = ctx.ctor_idx(&tpl.name).unwrap();
+                Space::Ctor(Ctor::Tag(idx, tag), vec![Space::from_tpl(&tpl.body, ctx)])
+            }
+            Tpl::Lit(Lit::Int(lit)) => Space::Ctor(Ctor::Int(lit.val), Vec::new()),
+            Tpl::Lit(Lit::Str(lit)) => Space::Ctor(Ctor::Str(lit.val.clone()), Vec::new()),
+            Tpl::Lit(_) => unreachable!("Only integer and string literals are parsed as patterns"),
+            Tpl::Ann(tpl) => Space::from_tpl(&tpl.tpl, ctx),
+        }
+    }
+
+    pub fn show(&self, ctx: &Ctx) -> String {
+        fn items(space: &Space, ctx: &Ctx, res: &mut Vec<String>) {
+            match space {
+                Space::Ctor(Ctor::Pair, args) => {
+                    items(&args[0], ctx, res);
+                    res.push(args[1].show(ctx));
+                }
+                space => res.push(space.show(ctx)),
+            }
+        }
+
+        match self {
+            Space::Wild => "_".to_string(),
+            Space::Ctor(Ctor::Unit, _) => "()".to_string(),
+            Space::Ctor(Ctor::Int(val), _) => val.to_string(),
+            Space::Ctor(Ctor::Str(val), _) => format!("{val:?}"),
+            Space::Ctor(Ctor::Pair, _) => {
+                let mut res = Vec::new();
+                items(self, ctx, &mut res);
+                format!("({})", res.join(", "))
+            }
+            Space::Ctor(Ctor::Tag(_, tag), args) => {
+                let name = ctx.tag_name(*tag);
+                match &args[0] {
+                    Space::Wild if ctx.is_nullary(name) => name.to_string(),
+                    Space::Ctor(Ctor::Tag(..), _) => format!("{name} ({})", args[0].show(ctx)),
+                    arg => format!("{name} {}", arg.show(ctx)),
+                }
+            }
+        }
+    }
+}
+
+// rows starting with the constructor, with its arguments in place of it
+fn specialize(rows: &[Vec<Space>], ctor: &Ctor) -> Vec<Vec<Space>> {
+    rows.iter()
+        .filter_map(|row| match &row[0] {
+            Space::Wild => {
+                let mut res = vec![Space::Wild; ctor.arity()];
+                res.extend_from_slice(&row[1..]);
+                Some(res)
+            }
+            Space::Ctor(other, args) if other == ctor => {
+                let mut res = args.clone();
+                res.extend_from_slice(&row[1..]);
+                Some(res)
+            }
+            Space::Ctor(..) => None,
+        })
+        .collect()
+}
+
+// rows starting with a wildcard, without it
+fn default(rows: &[Vec<Space>]) -> Vec<Vec<Space>> {
+    rows.iter()
+        .filter(|row| matches!(row[0], Space::Wild))
+        .map(|row| row[1..].to_vec())
+        .collect()
+}
+
+// puts the arguments of the constructor back in its place
+fn rebuild(ctor: Ctor, mut witness: Vec<Space>) -> Vec<Space> {
+    let rest = witness.split_off(ctor.arity());
+    let mut res = vec![Space::Ctor(ctor, witness)];
+    res.extend(rest);
+    res
+}
+
+// returns some values matched by the row, but not by any of the rows before it
+pub fn useful(rows: &[Vec<Space>], row: &[Space], ctx: &Ctx) -> Option<Vec<Space>> {
+    let (head, rest) = match row.split_first() {
+        Some(x) => x,
+        None => return rows.is_empty().then(Vec::new),
+    };
+
+    match head {
+        Space::Ctor(ctor, args) => {
+            let mut row = args.clone();
+            row.extend_from_slice(rest);
+            useful(&specialize(rows, ctor), &row, ctx).map(|w| rebuild(ctor.clone(), w))
+        }
+        Space::Wild => {
+            let mut heads = Vec::new();
+            for row in rows.iter() {
+                if let Space::Ctor(ctor, _) = &row[0] {
+                    if !heads.contains(ctor) {
+                        heads.push(ctor.clone());
+                    }
+                }
+            }
+
+            let all = heads.first().and_then(|ctor| ctor.siblings(ctx));
+            let missing = all
+                .as_ref()
+                .and_then(|all| all.iter().find(|ctor| !heads.contains(ctor)));
+
+            match (all.as_ref(), missing) {
+                (Some(all), None) => all.iter().find_map(|ctor| {
+                    let mut row = vec![Space::Wild; ctor.arity()];
+                    row.extend_from_slice(rest);
+                    useful(&specialize(rows, ctor), &row, ctx).map(|w| rebuild(ctor.clone(), w))
+                }),
+                (_, missing) => useful(&default(rows), rest, ctx).map(|w| {
+                    let head = match missing {
+                        Some(ctor) => Space::Ctor(ctor.clone(), vec![Space::Wild; ctor.arity()]),
+                        None => Space::Wild,
+                    };
+                    let mut res = vec![head];
+                    res.extend(w);
+                    res
+                }),
+            }
+        }
+    }
+}
@@ -1,5 +1,6 @@
 mod ctx;
 mod err;
+mod exhaust;
 mod verify;
 
-pub use verify::verify;
+pub use verify::{verify, Verified};
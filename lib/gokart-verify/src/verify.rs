@@ -1,12 +1,14 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use crate::{
-    ctx::{Ctx, TypeExt},
-    err::{VerifyErr, VerifyRes},
+    ctx::{Ctx, Scheme, TypeExt},
+    err::{VerifyErr, VerifyRes, VerifyWarn},
+    exhaust::{useful, Space},
 };
 use gokart_core::{
-    Abs, App, Ast, Branch, Case, ConTerm, Cond, Def, EmptyTerm, Let, Letrec, Lit, LocExt, Name,
-    Opr, PairTerm, Tag, Term, Type, TypeDef, TypeIdx,
+    groups, Abs, AnnTerm, App, Ast, Binding, Branch, Case, ConTerm, Cond, Def, DoubleNegs,
+    EmptyTerm, Group, Let, Letrec, Lit, Loc, LocExt, Name, Neg, Opr, PairTerm, Section, Seq, Term,
+    Tpl, Type, TypeDef,
 };
 
 trait Verify<'a> {
@@ -27,27 +29,23 @@ impl<'a> Verify<'a> for PairTerm<'a> {
 
 impl<'a> Verify<'a> for Name<'a> {
     fn verify(&self, ctx: &mut Ctx<'a>) -> VerifyRes<Type> {
-        Ok(ctx.var(self)?.clone())
+        ctx.var(self)
     }
 }
 
 impl<'a> Verify<'a> for Lit<'a> {
     fn verify(&self, ctx: &mut Ctx<'a>) -> VerifyRes<Type> {
-        match self {
-            Lit::Int(_) => Ok(ctx.int_ty()),
-            Lit::Double(_) => Ok(ctx.double_ty()),
-            Lit::Str(_) => Ok(ctx.str_ty()),
-        }
+        Ok(ctx.lit_ty(self))
     }
 }
 
 impl<'a> Verify<'a> for ConTerm<'a> {
     fn verify(&self, ctx: &mut Ctx<'a>) -> VerifyRes<Type> {
         let ty = self.body.verify(ctx)?;
-        let (expected_ty, new_ty) = ctx.ctor(&self.name)?;
+        let (expected_ty, new_ty, _) = ctx.ctor(&self.name)?;
 
-        ctx.resolve_apply(&expected_ty, &ty, &self.body.loc())?;
-        Ok(Type::Prim(new_ty))
+        ctx.resolve(&expected_ty, &ty, &self.body.loc())?;
+        Ok(new_ty)
     }
 }
 
@@ -57,41 +55,63 @@ impl<'a> Verify<'a> for Opr<'a> {
         let right_ty = self.right.verify(ctx)?;
         let (expected_left_ty, expected_right_ty, res_ty) = ctx.opr(&self.name)?;
 
-        ctx.resolve_apply(&expected_left_ty, &left_ty, &self.left.loc())?;
-        ctx.resolve_apply(&expected_right_ty, &right_ty, &self.right.loc())?;
+        ctx.resolve(&expected_left_ty, &left_ty, &self.left.loc())?;
+        ctx.resolve(&expected_right_ty, &right_ty, &self.right.loc())?;
         Ok(res_ty)
     }
 }
 
+impl<'a> Verify<'a> for Section<'a> {
+    fn verify(&self, ctx: &mut Ctx<'a>) -> VerifyRes<Type> {
+        let (left_ty, right_ty, res_ty) = ctx.opr(&self.name)?;
+
+        // the missing operands become the arguments of the function
+        let mut args = Vec::new();
+        for (term, expected_ty) in [(&self.left, left_ty), (&self.right, right_ty)] {
+            match term {
+                Some(term) => {
+                    let ty = term.verify(ctx)?;
+                    ctx.resolve(&expected_ty, &ty, &term.loc())?;
+                }
+                None => args.push(expected_ty),
+            }
+        }
+
+        Ok(args
+            .into_iter()
+            .rfold(res_ty, |acc, arg| Type::func(arg, acc)))
+    }
+}
+
+impl<'a> Verify<'a> for Neg<'a> {
+    fn verify(&self, ctx: &mut Ctx<'a>) -> VerifyRes<Type> {
+        let ty = self.term.verify(ctx)?;
+
+        // the operand may become known later, see `Ctx::double_negs`
+        match (ctx.find(&ty), ctx.double_ty()) {
+            (Type::Prim(idx), Type::Prim(double)) if idx == double => (),
+            (Type::Free(_), _) => (),
+            _ => ctx.resolve(&ctx.int_ty(), &ty, &self.term.loc())?,
+        }
+        ctx.neg(self.loc, self.term.loc(), ty.clone());
+        Ok(ty)
+    }
+}
+
 impl<'a> Verify<'a> for App<'a> {
     fn verify(&self, ctx: &mut Ctx<'a>) -> VerifyRes<Type> {
         let head_ty = self.head.verify(ctx)?;
         let body_ty = self.body.verify(ctx)?;
 
-        match head_ty {
+        match ctx.find(&head_ty) {
             Type::Func(from_ty, into_ty) => {
-                let subst = ctx.resolve_apply(&from_ty, &body_ty, &self.body.loc())?;
-                Ok(into_ty.apply(&subst))
+                ctx.resolve(&from_ty, &body_ty, &self.body.loc())?;
+                Ok(*into_ty)
             }
-            Type::Free(idx) => match body_ty.contains_ft(idx) {
-                false => {
-                    let ft = ctx.next_ft();
-                    ctx.resolve_apply(
-                        &Type::Free(idx),
-                        &Type::Func(body_ty.clone().ptr(), ft.clone().ptr()),
-                        &self.head.loc(),
-                    )?;
-                    Ok(ft)
-                }
-                true => Err(VerifyErr::InfiniteType(self.head.loc().into_span())),
-            },
             ty => {
-                let ft = ctx.next_ft().ptr();
-                Err(VerifyErr::TypeMismatch(
-                    self.head.loc().into_span(),
-                    Type::Func(body_ty.ptr(), ft).show(ctx),
-                    ty.show(ctx),
-                ))
+                let ft = ctx.next_ft();
+                ctx.resolve(&Type::func(body_ty, ft.clone()), &ty, &self.head.loc())?;
+                Ok(ft)
             }
         }
     }
@@ -103,8 +123,8 @@ impl<'a> Verify<'a> for Cond<'a> {
         let left_ty = self.left.verify(ctx)?;
         let right_ty = self.right.verify(ctx)?;
 
-        ctx.resolve_apply(&ctx.int_ty(), &cond_ty, &self.loc)?;
-        ctx.resolve_apply(&left_ty, &right_ty, &self.loc)?;
+        ctx.resolve(&ctx.bool_ty(), &cond_ty, &self.cond.loc())?;
+        ctx.resolve(&left_ty, &right_ty, &self.loc)?;
 
         Ok(left_ty)
     }
@@ -113,76 +133,113 @@ impl<'a> Verify<'a> for Cond<'a> {
 impl<'a> Verify<'a> for Abs<'a> {
     fn verify(&self, ctx: &mut Ctx<'a>) -> VerifyRes<Type> {
         let ft = ctx.next_ft();
-        let prev_ty = ctx.push_var(&self.arg, ft.clone());
+        ctx.enter_scope();
+        let (vars, tys) = ft.clone().split(&self.arg, ctx)?;
+        verify_irrefutable(&self.arg, ctx)?;
+        let prev_vars = ctx.push_vars(&vars, tys);
+
         let body_ty = self.body.verify(ctx)?;
-        let arg_ty = ctx.var(&self.arg)?.clone().ptr();
-        ctx.pop_var(&self.arg, prev_ty);
 
-        Ok(Type::Func(arg_ty, body_ty.ptr()))
+        ctx.pop_vars(&vars, prev_vars);
+        ctx.leave_scope();
+        Ok(Type::Func(ft.ptr(), body_ty.ptr()))
     }
 }
 
-fn verify_branch<'a>(branch: &Branch<'a>, ctx: &mut Ctx<'a>) -> VerifyRes<(Tag, TypeIdx, Type)> {
-    let (from_ty, into_ty) = ctx.ctor(&branch.con)?;
-    let tag = ctx.tag(&branch.con)?;
-
+fn verify_branch<'a>(branch: &Branch<'a>, from_ty: Type, ctx: &mut Ctx<'a>) -> VerifyRes<Type> {
+    ctx.enter_scope();
     let (vars, tys) = from_ty.split(&branch.tpl, ctx)?;
     let prev_vars = ctx.push_vars(&vars, tys);
 
+    if let Some(guard) = &branch.guard {
+        let guard_ty = guard.verify(ctx)?;
+        ctx.resolve(&ctx.bool_ty(), &guard_ty, &guard.loc())?;
+    }
     let res = branch.body.verify(ctx)?;
 
     ctx.pop_vars(&vars, prev_vars);
-    Ok((tag, into_ty, res))
+    ctx.leave_scope();
+    Ok(res)
 }
 
 impl<'a> Verify<'a> for Case<'a> {
     fn verify(&self, ctx: &mut Ctx<'a>) -> VerifyRes<Type> {
-        let mut it = self.branches.iter();
-        let mut tags = HashSet::new();
-
-        let (into_ty, res_ty) = match it.next() {
-            Some(first) => {
-                let (tag, into_ty, res_ty) = verify_branch(first, ctx)?;
-                tags.insert(tag);
-
-                it.try_fold((into_ty, res_ty), |(into_ty, res_ty), branch| {
-                    let (tag, branch_into_ty, branch_ty) = verify_branch(branch, ctx)?;
-
-                    let span = branch.loc.into_span();
-                    if !tags.insert(tag) {
-                        return Err(VerifyErr::BranchRedefinition(span));
-                    }
-                    if into_ty != branch_into_ty {
-                        return Err(VerifyErr::InvalidBranchesType(span));
-                    }
-
-                    ctx.resolve_apply(&res_ty, &branch_ty, &branch.loc)?;
-                    Ok((into_ty, res_ty))
-                })?
+        // the scrutinee goes first, so its type arguments reach the patterns
+        let cond_ty = self.cond.verify(ctx)?;
+        let mut res_ty: Option<Type> = None;
+        let mut rows = Vec::new();
+
+        for branch in self.branches.iter() {
+            let branch_ty = verify_branch(branch, cond_ty.clone(), ctx)?;
+            match &res_ty {
+                Some(res_ty) => ctx.resolve(res_ty, &branch_ty, &branch.loc)?,
+                None => res_ty = Some(branch_ty),
+            }
+
+            let row = vec![Space::from_tpl(&branch.tpl, ctx)];
+            if useful(&rows, &row, ctx).is_none() {
+                return Err(VerifyErr::RedundantBranch(branch.loc.into_span()));
+            }
+            // a guard may fail, so the branch doesn't cover anything by itself
+            if branch.guard.is_none() {
+                rows.push(row);
             }
+        }
+
+        if let Some(witness) = useful(&rows, &[Space::Wild], ctx) {
+            return Err(VerifyErr::BranchNotCovered(
+                self.loc.into_span(),
+                format!("pattern {} is not matched", witness[0].show(ctx)),
+            ));
+        }
+
+        match res_ty {
+            Some(res_ty) => Ok(res_ty),
             None => unreachable!(
                 "We always have at least one branch. This is checked at the parsing stage"
             ),
-        };
-
-        let cons = ctx.ty_cons(into_ty);
-        if &tags != cons {
-            return Err(VerifyErr::BranchNotCovered(self.loc.into_span()));
         }
+    }
+}
 
-        let cond_ty = self.cond.verify(ctx)?;
-        ctx.resolve_apply(&Type::Prim(into_ty), &cond_ty, &self.cond.loc())?;
+// the location of a part of the pattern that inspects the value
+fn find_refutable<'b>(tpl: &Tpl<'b>) -> Option<Loc<'b>> {
+    match tpl {
+        Tpl::Empty(_) | Tpl::Var(_) | Tpl::Wild(_) => None,
+        Tpl::Pair(tpl) => find_refutable(&tpl.left).or_else(|| find_refutable(&tpl.right)),
+        Tpl::As(tpl) => find_refutable(&tpl.tpl),
+        Tpl::Con(tpl) => Some(tpl.loc),
+        Tpl::Lit(lit) => Some(lit.loc()),
+        Tpl::Ann(tpl) => find_refutable(&tpl.tpl),
+    }
+}
 
-        Ok(res_ty)
+// bindings may only use patterns matching all the values of the type
+fn verify_irrefutable<'a>(tpl: &Tpl<'a>, ctx: &Ctx<'a>) -> VerifyRes<()> {
+    let rows = [vec![Space::from_tpl(tpl, ctx)]];
+
+    match useful(&rows, &[Space::Wild], ctx) {
+        Some(witness) => Err(VerifyErr::RefutablePattern(
+            tpl.loc().into_span(),
+            format!("pattern {} is not matched", witness[0].show(ctx)),
+        )),
+        None => Ok(()),
     }
 }
 
 impl<'a> Verify<'a> for Let<'a> {
     fn verify(&self, ctx: &mut Ctx<'a>) -> VerifyRes<Type> {
+        // annotations in the pattern are checked before the generalization
+        ctx.enter_level();
         let ty = self.term.verify(ctx)?;
-
+        ctx.enter_scope();
         let (vars, tys) = ty.split(&self.tpl, ctx)?;
-        let prev_vars = ctx.push_vars(&vars, tys);
+        ctx.leave_scope();
+        ctx.leave_level();
+        verify_irrefutable(&self.tpl, ctx)?;
+
+        let schemes = tys.iter().map(|ty| ctx.generalize(ty)).collect();
+        let prev_vars = ctx.push_schemes(&vars, schemes);
 
         let res = self.body.verify(ctx);
 
@@ -191,20 +248,54 @@ impl<'a> Verify<'a> for Let<'a> {
     }
 }
 
+// the locations of the bound names in the same order as `split` returns them
+fn name_locs<'b>(tpl: &Tpl<'b>, res: &mut Vec<Loc<'b>>) {
+    match tpl {
+        Tpl::Empty(_) | Tpl::Wild(_) | Tpl::Lit(_) => (),
+        Tpl::Var(name) => res.push(name.loc),
+        Tpl::Pair(tpl) => {
+            name_locs(&tpl.left, res);
+            name_locs(&tpl.right, res);
+        }
+        Tpl::As(tpl) => {
+            res.push(tpl.name.loc);
+            name_locs(&tpl.tpl, res);
+        }
+        Tpl::Con(tpl) => name_locs(&tpl.body, res),
+        Tpl::Ann(tpl) => name_locs(&tpl.tpl, res),
+    }
+}
+
 impl<'a> Verify<'a> for Letrec<'a> {
     fn verify(&self, ctx: &mut Ctx<'a>) -> VerifyRes<Type> {
+        if let Some(loc) = find_refutable(&self.tpl) {
+            return Err(VerifyErr::LetrecRefutablePattern(loc.into_span()));
+        }
+
+        ctx.enter_level();
         let ft = ctx.next_ft();
 
-        let (vars, fts) = ft.clone().split(&self.tpl, ctx)?;
-        let prev_vars = ctx.push_vars(&vars, fts.clone());
+        ctx.enter_scope();
+        let (vars, fts) = ft.split(&self.tpl, ctx)?;
+        let prev_vars = ctx.push_vars(&vars, fts);
 
         let ty = self.term.verify(ctx)?;
         let (_, tys) = ty.split(&self.tpl, ctx)?;
 
-        fts.iter()
-            .zip(tys.iter())
-            .map(|(ft, ty)| ctx.resolve_apply(ft, ty, &self.loc))
-            .collect::<VerifyRes<Vec<_>>>()?;
+        let mut locs = Vec::new();
+        name_locs(&self.tpl, &mut locs);
+        for ((var, ty), loc) in vars.iter().zip(tys.iter()).zip(locs) {
+            let var_ty = ctx.mono(var);
+            ctx.resolve(&var_ty, ty, &loc)?;
+        }
+        ctx.leave_scope();
+        ctx.leave_level();
+
+        // the definitions are monomorphic inside the group and generalized after it
+        let tys = vars.iter().map(|var| ctx.mono(var)).collect::<Vec<_>>();
+        ctx.pop_vars(&vars, prev_vars);
+        let schemes = tys.iter().map(|ty| ctx.generalize(ty)).collect();
+        let prev_vars = ctx.push_schemes(&vars, schemes);
 
         let res = self.body.verify(ctx);
 
@@ -213,6 +304,26 @@ impl<'a> Verify<'a> for Letrec<'a> {
     }
 }
 
+impl<'a> Verify<'a> for Seq<'a> {
+    fn verify(&self, ctx: &mut Ctx<'a>) -> VerifyRes<Type> {
+        let ty = self.first.verify(ctx)?;
+        ctx.discard(self.first.loc(), ty);
+        self.second.verify(ctx)
+    }
+}
+
+impl<'a> Verify<'a> for AnnTerm<'a> {
+    fn verify(&self, ctx: &mut Ctx<'a>) -> VerifyRes<Type> {
+        let ty = self.term.verify(ctx)?;
+        ctx.enter_scope();
+        let ann_ty = ctx.ann_ty(&self.ty)?;
+
+        ctx.resolve(&ann_ty, &ty, &self.ty.loc())?;
+        ctx.leave_scope();
+        Ok(ann_ty)
+    }
+}
+
 impl<'a> Verify<'a> for Term<'a> {
     fn verify(&self, ctx: &mut Ctx<'a>) -> VerifyRes<Type> {
         match self {
@@ -228,6 +339,10 @@ impl<'a> Verify<'a> for Term<'a> {
             Term::Case(term) => term.verify(ctx),
             Term::Let(term) => term.verify(ctx),
             Term::Letrec(term) => term.verify(ctx),
+            Term::Seq(term) => term.verify(ctx),
+            Term::Section(term) => term.verify(ctx),
+            Term::Neg(term) => term.verify(ctx),
+            Term::Ann(term) => term.verify(ctx),
         }
     }
 }
@@ -238,37 +353,785 @@ pub trait Apply<'a> {
 
 impl<'a> Apply<'a> for TypeDef<'a> {
     fn apply(&self, ctx: &mut Ctx<'a>) -> VerifyRes<()> {
-        let uty = ctx.add_ty(&self.name)?;
+        // the name itself is registered beforehand, see `verify`
+        let uty = ctx.ty(&self.name)?;
+        if self.cons.is_empty() {
+            return Err(VerifyErr::EmptyType(
+                self.loc.into_span(),
+                self.name.val.to_string(),
+            ));
+        }
+
+        let mut params = HashMap::new();
+        let mut fts = Vec::new();
+        for param in self.params.iter() {
+            let ft = ctx.next_ft();
+            if params.insert(param.val, ft.clone()).is_some() {
+                return Err(VerifyErr::TypeVarRedefinition(
+                    param.loc.into_span(),
+                    param.val.to_string(),
+                ));
+            }
+            fts.push(ft);
+        }
+
+        let into = Type::app(uty, fts.clone());
+        let fts = fts
+            .into_iter()
+            .filter_map(|ft| match ft {
+                Type::Free(idx) => Some(idx),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
 
         for con in self.cons.iter() {
             let mut it = con.args.iter();
             let from = match it.next() {
-                Some(init) => it.fold(ctx.prim_ty(init), |acc, p| {
-                    Ok(Type::Pair(acc?.ptr(), ctx.prim_ty(p)?.ptr()))
+                Some(init) => it.fold(ctx.type_expr(init, &params), |acc, p| {
+                    Ok(Type::Pair(acc?.ptr(), ctx.type_expr(p, &params)?.ptr()))
                 })?,
                 None => ctx.unit_ty(),
             };
-            ctx.add_ctor(&con.name, from, uty)?;
+            let scheme = Scheme {
+                fts: fts.clone(),
+                ty: Type::func(from, into.clone()),
+            };
+            ctx.add_ctor(&con.name, scheme, uty)?;
         }
         Ok(())
     }
 }
 
-impl<'a> Apply<'a> for Def<'a> {
-    fn apply(&self, ctx: &mut Ctx<'a>) -> VerifyRes<()> {
-        match self {
-            Def::TypeDef(type_def) => type_def.apply(ctx),
+// the groups it refers to are already generalized, so a top-level definition
+// is polymorphic everywhere but in the mutually recursive ones of its group
+fn verify_group<'a>(
+    group: &Group,
+    bindings: &[Binding<'_, 'a>],
+    ctx: &mut Ctx<'a>,
+) -> VerifyRes<()> {
+    let defs = group
+        .idxs
+        .iter()
+        .map(|idx| bindings[*idx])
+        .collect::<Vec<_>>();
+
+    ctx.enter_level();
+    if group.rec {
+        for def in defs.iter() {
+            let ft = ctx.next_ft();
+            ctx.push_var(def.name, ft);
         }
     }
+
+    let mut tys = Vec::new();
+    for def in defs.iter() {
+        let ty = def.term.verify(ctx)?;
+        if group.rec {
+            let var_ty = ctx.mono(def.name);
+            ctx.resolve(&var_ty, &ty, &def.name.loc)?;
+        }
+        tys.push(ty);
+    }
+    ctx.leave_level();
+
+    // stay in scope for the rest of the program
+    for (def, ty) in defs.iter().zip(tys) {
+        let scheme = ctx.generalize(&ty);
+        ctx.push_scheme(def.name, scheme);
+    }
+    Ok(())
 }
 
-pub fn verify(ast: &Ast) -> VerifyRes<()> {
+// what the later stages learn from the verify step
+#[derive(Debug)]
+pub struct Verified {
+    pub warns: Vec<VerifyWarn>,
+    pub double_negs: DoubleNegs,
+}
+
+pub fn verify(ast: &Ast) -> VerifyRes<Verified> {
     let mut ctx = Ctx::with_predef();
 
+    // types may refer to the ones declared later
     for def in ast.defs.iter() {
-        def.apply(&mut ctx)?;
+        if let Def::TypeDef(type_def) = def {
+            ctx.add_ty(&type_def.name, type_def.params.len())?;
+        }
+    }
+
+    let mut names = HashSet::new();
+    for def in ast.defs.iter() {
+        match def {
+            Def::TypeDef(type_def) => type_def.apply(&mut ctx)?,
+            Def::OprDef(opr_def) => {
+                ctx.check_opr(&opr_def.name)?;
+                if !names.insert(opr_def.name.val) {
+                    return Err(VerifyErr::OprRedefinition(
+                        opr_def.name.loc.into_span(),
+                        opr_def.name.to_string(),
+                    ));
+                }
+            }
+            Def::ValDef(val_def) => {
+                if !names.insert(val_def.name.val) {
+                    return Err(VerifyErr::ValueRedefinition(
+                        val_def.name.loc.into_span(),
+                        val_def.name.to_string(),
+                    ));
+                }
+            }
+        }
+    }
+
+    // definitions may refer to each other regardless of their order
+    let bindings = ast.bindings();
+    for group in groups(&bindings) {
+        verify_group(&group, &bindings, &mut ctx)?;
+    }
+
+    // the program without the final expression evaluates `main`
+    if let Term::Var(name) = &ast.body {
+        if let (Err(VerifyErr::UnknownName(..)), "main") = (ctx.var(name), name.val) {
+            return Err(VerifyErr::MissingMain(name.loc.into_span()));
+        }
     }
     ast.body.verify(&mut ctx)?;
 
-    Ok(())
+    let double_negs = ctx.double_negs()?;
+    Ok(Verified {
+        warns: ctx.warns(),
+        double_negs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gokart_parse::parse;
+
+    fn check(input: &str) -> VerifyRes<()> {
+        match parse(input) {
+            Ok(ast) => verify(&ast).map(|_| ()),
+            Err(e) => panic!("Unexpected error: {e:?}"),
+        }
+    }
+
+    fn assert_ok(input: &str) {
+        if let Err(e) = check(input) {
+            panic!("Unexpected error for {input}: {e:?}");
+        }
+    }
+
+    // the pattern is matched against the error the same way `matches!` does
+    macro_rules! assert_err {
+        ($input:expr, $pat:pat $(if $guard:expr)?) => {{
+            let input: &str = &$input;
+            match check(input) {
+                Err($pat) $(if $guard)? => (),
+                res => panic!("Expected {} for {input}, got {res:?}", stringify!($pat)),
+            }
+        }};
+    }
+
+    #[test]
+    fn ok_let_polymorphism() {
+        let inputs = [
+            r#"let id = \x -> x; in (id 1, id "s")"#,
+            r#"let k = \x y -> x; in (k 1 "s", k "s" 1.0)"#,
+            r#"letrec f = \x -> x; in (f 1, f "s")"#,
+            r#"letrec len = \n -> if n == 0 then 0 else len (n - 1); in len 3"#,
+            r#"infixl 1 |> = \x f -> f x; (1 |> i2s) |> print"#,
+        ];
+
+        for input in inputs {
+            assert_ok(input);
+        }
+    }
+
+    #[test]
+    fn fail_lambda_monomorphism() {
+        let input = r#"(\id -> (id 1, id "s")) (\x -> x)"#;
+        assert!(check(input).is_err());
+    }
+
+    #[test]
+    fn fail_no_generalization_of_env_types() {
+        let input = r#"\x -> let y = x; in (y + 1, y ++ "s")"#;
+        assert!(check(input).is_err());
+    }
+
+    fn assert_infinite(input: &str) {
+        assert_err!(input, VerifyErr::InfiniteType(..));
+    }
+
+    fn assert_mismatch(input: &str) {
+        assert_err!(input, VerifyErr::TypeMismatch(..));
+    }
+
+    #[test]
+    fn fail_infinite_types() {
+        assert_infinite(r#"\x -> x x"#);
+        assert_infinite(r#"\f -> \x -> f (f, x)"#);
+        assert_infinite(r#"letrec f = \x -> f; in f"#);
+        assert_infinite(
+            r#"\x -> \y -> let _ = if True then x else (y, y); in if True then y else (x, 1)"#,
+        );
+    }
+
+    #[test]
+    fn fail_bindings_are_consistent() {
+        assert_mismatch(r#"\f -> (f 1, f "s")"#);
+        assert_mismatch(r#"(\x -> \y -> if True then x else y) 1 "s""#);
+        assert_mismatch(r#"\p -> let (a, b) = p; in if True then (a, a) else (1, "s")"#);
+        assert_mismatch(r#"\x -> if x then x + 1 else 0"#);
+    }
+
+    #[test]
+    fn ok_bindings_propagate() {
+        let inputs = [
+            r#"\f -> \g -> \x -> f (g x)"#,
+            r#"(\x -> \y -> if True then x else y) 1 2"#,
+            r#"\x -> \y -> let z = if True then x else y; in z + 1"#,
+            r#"let twice = \f x -> f (f x); in (twice (\x -> x + 1) 1, twice (\s -> s ++ "!") "s")"#,
+        ];
+
+        for input in inputs {
+            assert_ok(input);
+        }
+    }
+
+    #[test]
+    fn ok_parametric_data() {
+        let input = r#"
+            data List a = Nil | Cons a (List a)
+            data Pair a b = Pair a b
+            letrec map = \f xs -> case xs of
+                | Nil _ -> Nil ();
+                | Cons (x, rest) -> Cons (f x, map f rest);
+            ; in
+            let len = \xs -> case xs of
+                | Nil _ -> 0;
+                | Cons (_x, _rest) -> 1;
+            ; in
+            let ints = Cons (1, Nil ()); in
+            let strs = map i2s ints; in
+            let _ = Pair (len ints, len strs); in
+            case strs of
+                | Nil _ -> "";
+                | Cons (s, _rest) -> s ++ "!";
+        "#;
+
+        assert_ok(input);
+    }
+
+    #[test]
+    fn fail_parametric_data() {
+        assert_mismatch(
+            r#"
+            data List a = Nil | Cons a (List a)
+            let xs = Cons (1, Cons ("s", Nil ())); in xs
+        "#,
+        );
+        assert_mismatch(
+            r#"
+            data Box a = Box a
+            case Box (1) of
+                | Box s -> s ++ "!";
+        "#,
+        );
+    }
+
+    #[test]
+    fn fail_kind_mismatch() {
+        let inputs = [
+            "data List a = Nil | Cons a List\n1",
+            "data Box a = Box (Int a)\n1",
+        ];
+
+        for input in inputs {
+            assert_err!(input, VerifyErr::KindMismatch(..));
+        }
+    }
+
+    #[test]
+    fn fail_bad_type_vars() {
+        assert_err!("data Box a = Box (Box b)\n1", VerifyErr::UnknownTypeVar(..));
+        assert_err!(
+            "data Pair a a = Pair a a\n1",
+            VerifyErr::TypeVarRedefinition(..)
+        );
+    }
+
+    #[test]
+    fn ok_type_exprs_in_fields() {
+        let input = r#"
+            data Handler a = Handler (a -> Str) (Int, a)
+            let h = Handler (i2s, (1, 2)); in
+            case h of
+                | Handler (f, (_n, x)) -> print (f x);
+        "#;
+
+        assert_ok(input);
+
+        assert_mismatch(
+            r#"
+            data Handler = Handler (Int -> Str)
+            let h = Handler (\x -> x + 1); in h
+        "#,
+        );
+    }
+
+    #[test]
+    fn ok_forward_type_references() {
+        let input = r#"
+            data A = MkA B
+            data B = MkB A | NoB
+            data Forest a = Forest (List (Tree a))
+            data Tree a = Tree a (Forest a)
+            data List a = Nil | Cons a (List a)
+            let a = MkA (MkB (MkA (NoB ()))); in
+            let t = Tree (1, Forest (Nil ())); in
+            (a, t)
+        "#;
+
+        assert_ok(input);
+    }
+
+    #[test]
+    fn fail_empty_type() {
+        assert_err!("data Void =\n1", VerifyErr::EmptyType(..));
+    }
+
+    #[test]
+    fn ok_type_annotations() {
+        let inputs = [
+            r#"let n : Int = 1; in n + 1"#,
+            r#"let id : a -> a = \x -> x; in (id 1, id "s")"#,
+            r#"let (a, b : Str) : (Int, Str) = (1, "s"); in a"#,
+            r#"(\(x : Double) -> x +% 1.0) 2.0"#,
+            r#"letrec f : Int -> Int = \n -> if n == 0 then 0 else f (n - 1); in f 3"#,
+            r#"((\x -> x) : Int -> Int)"#,
+            r#"((\x -> x) : a -> a) 1"#,
+            r#"(\(x : a) -> x) 1"#,
+            r#"let f : a -> b -> a = \x y -> x; in (f 1 "s", f "s" 1)"#,
+        ];
+
+        for input in inputs {
+            assert_ok(input);
+        }
+    }
+
+    #[test]
+    fn fail_type_annotations() {
+        let inputs = [
+            (r#"let n : Str = 1; in n"#, 8),
+            (r#"\(x : Double) -> x + 1"#, 17),
+            (r#"(1 : Str)"#, 5),
+            (r#"let p : (a, a) = (1, "s"); in p"#, 9),
+            (r#"let id : a -> a = \x -> x + 1; in id"#, 9),
+            (r#"let f : a -> b = \x -> x; in f 1"#, 8),
+            (r#"((\x -> x + 1) : a -> a)"#, 17),
+            (r#"(\(x : a) -> x + 1)"#, 7),
+            (r#"case 1 of | (x : a) -> x;"#, 17),
+        ];
+
+        for (input, offset) in inputs {
+            assert_err!(
+                input,
+                VerifyErr::TypeMismatch(span, ..) if span.offset() == offset
+            );
+        }
+    }
+
+    #[test]
+    fn ok_readable_mismatch() {
+        let inputs = [
+            (
+                r#"let f = \(g : Int -> Str) -> g 1; in f (\x -> x + 1)"#,
+                "Int -> Str",
+                "Int -> Int",
+                Some("Str is not compatible with Int"),
+            ),
+            (
+                r#"(\f -> f 1 2) (\x -> x ++ "s")"#,
+                "Int -> Int -> a",
+                "Str -> Str",
+                Some("Int is not compatible with Str"),
+            ),
+            (
+                r#"(\p -> let (f, x) = p; in f x) ((\g -> g 1) : (Int -> a) -> a, 1)"#,
+                "((Int -> a) -> a, Int -> a)",
+                "((Int -> a) -> a, Int)",
+                Some("Int -> a is not compatible with Int"),
+            ),
+            (r#"if 1 then 2 else 3"#, "Bool", "Int", None),
+        ];
+
+        for (input, expected, found, help) in inputs {
+            assert_err!(
+                input,
+                VerifyErr::TypeMismatch(_, e, f, h)
+                    if (e.as_str(), f.as_str(), h.as_deref()) == (expected, found, help)
+            );
+        }
+    }
+
+    const LIST: &str = "data List a = Nil | Cons (a, List a)\n";
+
+    #[test]
+    fn ok_nested_patterns() {
+        let inputs = [
+            "case xs of | Nil -> 0; | Cons (x, Nil) -> x; | Cons (x, Cons (y, _)) -> x + y;",
+            "case xs of | Cons (x, r as Cons _) -> x; | _ -> 0;",
+            "case xs of | Cons (_, Cons _) -> 1; | _ -> 0;",
+            "let (_, _) = (1, 2); in (\\_ _ -> 0) 1 2",
+            "case (xs, xs) of | (Nil, _) -> 0; | (_, Nil) -> 1; | (Cons _, Cons _) -> 2;",
+        ];
+
+        for input in inputs {
+            let input = format!("{LIST}let xs = Cons (1, Nil ()); in {input}");
+            assert_ok(&input);
+        }
+    }
+
+    #[test]
+    fn fail_redundant_branch() {
+        let inputs = [
+            "case xs of | _ -> 0; | Nil -> 1;",
+            "case xs of | Nil -> 0; | Cons _ -> 1; | _ -> 2;",
+        ];
+
+        for input in inputs {
+            let input = format!("{LIST}let xs = Nil (); in {input}");
+            assert_err!(input, VerifyErr::RedundantBranch(..));
+        }
+    }
+
+    #[test]
+    fn fail_branch_not_covered() {
+        let inputs = [
+            ("case xs of | Nil -> 0;", "pattern Cons _ is not matched"),
+            (
+                "case xs of | Nil -> 0; | Cons (_, Cons _) -> 1;",
+                "pattern Cons (_, Nil) is not matched",
+            ),
+            (
+                "case (xs, xs) of | (Nil, _) -> 0; | (_, Nil) -> 1;",
+                "pattern (Cons _, Cons _) is not matched",
+            ),
+        ];
+
+        for (input, help) in inputs {
+            let input = format!("{LIST}let xs = Nil (); in {input}");
+            assert_err!(input, VerifyErr::BranchNotCovered(_, h) if h == help);
+        }
+    }
+
+    #[test]
+    fn fail_refutable_pattern() {
+        let input = format!("{LIST}let Cons (x, _) = Nil (); in x");
+        assert_err!(
+            input,
+            VerifyErr::RefutablePattern(_, h) if h == "pattern Nil is not matched"
+        );
+    }
+
+    #[test]
+    fn fail_letrec_refutable_pattern() {
+        let inputs = [
+            format!("{LIST}letrec Cons (x, _) = Cons (1, Nil ()); in x"),
+            "letrec (x, 1) = (1, 1); in x".to_string(),
+        ];
+
+        for input in inputs {
+            assert_err!(input, VerifyErr::LetrecRefutablePattern(..));
+        }
+    }
+
+    #[test]
+    fn ok_literal_patterns() {
+        let inputs = [
+            r#"\n -> case n of | 0 -> "zero"; | 1 -> "one"; | _ -> "many";"#,
+            r#"\s -> case s of | "quit" -> 0; | _ -> 1;"#,
+            r#"\n -> case n of | -1 -> "minus one"; | 1 -> "one"; | _ -> "many";"#,
+            r#"\p -> case p of | (0, "a") -> 0; | (n, "a") -> n; | (_, _) -> 1;"#,
+        ];
+
+        for input in inputs {
+            assert_ok(input);
+        }
+    }
+
+    #[test]
+    fn fail_literal_patterns() {
+        assert_mismatch(r#"\n -> case n of | 0 -> 0; | "s" -> 1; | _ -> 2;"#);
+
+        assert_err!(
+            r#"\n -> case n of | 0 -> 0; | 1 -> 1;"#,
+            VerifyErr::BranchNotCovered(_, h) if h == "pattern _ is not matched"
+        );
+
+        assert_err!(
+            r#"\s -> case s of | "a" -> 0; | "a" -> 1; | _ -> 2;"#,
+            VerifyErr::RedundantBranch(span) if span.offset() == 28
+        );
+    }
+
+    #[test]
+    fn ok_guards() {
+        let inputs = [
+            r#"\n -> case n of | x if x > 0 -> "pos"; | 0 -> "zero"; | _ -> "neg";"#,
+            r#"\p -> case p of | (a, b) if a == b -> 0; | (a, _) if a > 0 -> a; | _ -> 1;"#,
+            r#"data Maybe a = Nothing | Just a
+            \m -> case m of | Just (x) if x > 10 -> x; | Just (x) -> 0 - x; | Nothing -> 0;"#,
+        ];
+
+        for input in inputs {
+            assert_ok(input);
+        }
+    }
+
+    #[test]
+    fn fail_guards() {
+        assert_mismatch(r#"\n -> case n + 1 of | x if x -> 0; | _ -> 1;"#);
+        assert_mismatch(r#"\n -> case n of | x if x > 0 -> 0; | _ -> "s";"#);
+
+        assert_err!(
+            r#"\n -> case n of | x if x > 0 -> 0; | x if x <= 0 -> 1;"#,
+            VerifyErr::BranchNotCovered(_, h) if h == "pattern _ is not matched"
+        );
+
+        assert_err!(
+            r#"\n -> case n of | _ -> 0; | x if x > 0 -> 1;"#,
+            VerifyErr::RedundantBranch(span) if span.offset() == 26
+        );
+    }
+
+    #[test]
+    fn ok_lambda_patterns() {
+        let inputs = [
+            r#"(\(a, b) c -> a + b + c) (1, 2) 3"#,
+            r#"(\(a, _) (_, b) -> a ++ b) ("a", 1) (2, "b")"#,
+            r#"(\(x : Int, s as (_, _)) -> s) (1, (2, 3))"#,
+            r#"data Box a = Box a
+            let unbox = \(Box x) -> x; in (unbox (Box (1)), unbox (Box ("s")))"#,
+        ];
+
+        for input in inputs {
+            assert_ok(input);
+        }
+    }
+
+    #[test]
+    fn fail_lambda_patterns() {
+        assert_mismatch(r#"(\(a, b) -> a + b) 1"#);
+        assert_mismatch(r#"(\(a, b) -> a + b) (1, "s")"#);
+
+        let input = format!("{LIST}\\(Cons (x, _)) -> x");
+        assert_err!(
+            input,
+            VerifyErr::RefutablePattern(_, h) if h == "pattern Nil is not matched"
+        );
+    }
+
+    #[test]
+    fn ok_function_definitions() {
+        let inputs = [
+            r#"let add x y = x + y; in add 1 2"#,
+            r#"let fst (a, _) = a; snd (_, b) = b; in (fst (1, "s"), snd (1, "s"))"#,
+            r#"letrec len n = if n == 0 then 0 else 1 + len (n - 1); in len 3"#,
+            r#"letrec even n = if n == 0 then True else odd (n - 1);
+                      odd n = if n == 0 then False else even (n - 1);
+               in even 4"#,
+        ];
+
+        for input in inputs {
+            assert_ok(input);
+        }
+    }
+
+    #[test]
+    fn fail_function_definitions() {
+        assert_err!(
+            r#"letrec loop x = loop; in loop"#,
+            VerifyErr::InfiniteType(span, _) if (span.offset(), span.len()) == (7, 4)
+        );
+    }
+
+    #[test]
+    fn ok_top_level_defs() {
+        let inputs = [
+            r#"main = even 4; even n = if n == 0 then True else odd (n - 1);
+               odd n = if n == 0 then False else even (n - 1);"#,
+            r#"pair = (id 1, id "s"); id x = x; main = pair;"#,
+            r#"infixl 1 |> = apply; apply x f = f x; main = 1 |> i2s |> print;"#,
+            r#"data List a = Nil | Cons (a, List a)
+               len xs = case xs of | Nil -> 0; | Cons (_, r) -> 1 + len r;;
+               len (Cons (1, Nil ()))"#,
+        ];
+
+        for input in inputs {
+            assert_ok(input);
+        }
+    }
+
+    #[test]
+    fn fail_top_level_defs() {
+        assert_err!(
+            "f x = x; f = 1; main = f;",
+            VerifyErr::ValueRedefinition(span, _) if span.offset() == 9
+        );
+
+        assert_err!("f x = x;", VerifyErr::MissingMain(span) if span.offset() == 8);
+
+        // definitions of the same group are monomorphic inside of it
+        assert_mismatch(r#"f x = (g 1, g "s"); g x = let _ = f x; in x; main = f;"#);
+    }
+
+    fn warns(input: &str) -> Vec<VerifyWarn> {
+        match parse(input).map(|ast| verify(&ast)) {
+            Ok(Ok(verified)) => verified.warns,
+            res => panic!("Unexpected result for {input}: {res:?}"),
+        }
+    }
+
+    #[test]
+    fn ok_do_blocks() {
+        let inputs = [
+            r#"do { print "a"; print "b"; 1 }"#,
+            r#"\f -> do { f 1; 2 }"#,
+            r#"do { let _ = 1; in print "a"; () }"#,
+        ];
+
+        for input in inputs {
+            assert!(warns(input).is_empty(), "Unexpected warnings for {input}");
+        }
+    }
+
+    #[test]
+    fn warn_discarded_values() {
+        let res = warns(r#"do { 1; print "a"; "s"; () }"#)
+            .into_iter()
+            .map(|warn| match warn {
+                VerifyWarn::DiscardedValue(span, ty) => (span.offset(), ty),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(vec![(5, "Int".to_string()), (19, "Str".to_string())], res);
+    }
+
+    #[test]
+    fn ok_sections() {
+        let inputs = [
+            r#"(+) 1 2 + (* 2) 3 + (10 -) 4"#,
+            r#"(\f -> f "a" "b") (++)"#,
+            r#"if (&&) True ((< 3) 2) then (|| False) else (False ||)"#,
+            r#"infixl 1 |> = \x f -> f x; main = (|> print) "s";"#,
+        ];
+
+        for input in inputs {
+            assert_ok(input);
+        }
+    }
+
+    #[test]
+    fn fail_sections() {
+        assert_mismatch(r#"(+ "s")"#);
+        assert_mismatch(r#"(+) 1 "s""#);
+        assert_mismatch(r#"(1 ++)"#);
+
+        assert_err!(
+            "(<+> 1)",
+            VerifyErr::UnknownOpr(span, _) if (span.offset(), span.len()) == (1, 3)
+        );
+    }
+
+    #[test]
+    fn ok_negation() {
+        let inputs = [
+            r#"\n f -> (-n, n-1, - f n, 2 - -n)"#,
+            r#"(-1.5, -(1.0 +% 2.0), \(x : Double) -> -x)"#,
+            r#"\n -> case n of | -1 -> 0; | _ -> -n;"#,
+        ];
+
+        for input in inputs {
+            assert_ok(input);
+        }
+    }
+
+    #[test]
+    fn ok_double_negations() {
+        let inputs = [
+            (r#"(\x -> -x) 1.5"#, vec![7]),
+            (r#"let neg = \x -> -x; in neg 1.5"#, vec![16]),
+            (r#"\n -> (-n, -(n +% 1.0), -2)"#, vec![7, 11]),
+            (r#"\f -> -f 1"#, vec![]),
+        ];
+
+        for (input, expected) in inputs {
+            match parse(input).map(|ast| verify(&ast)) {
+                Ok(Ok(verified)) => {
+                    let mut res = verified.double_negs.into_iter().collect::<Vec<_>>();
+                    res.sort();
+                    assert_eq!(expected, res, "for {input}");
+                }
+                res => panic!("Unexpected result for {input}: {res:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn fail_negation() {
+        assert_mismatch(r#"-"s""#);
+        assert_mismatch(r#"-True"#);
+        assert_mismatch(r#"(\x -> -x) "s""#);
+        // negations are not generalized, one of them can't be for both
+        assert_mismatch(r#"let neg = \x -> -x; in (neg 1, neg 1.5)"#);
+    }
+
+    #[test]
+    fn ok_unicode_names() {
+        let input = r#"data Ответ = Да | Нет
+            показать о = case о of | Да -> "да"; | Нет -> "нет";;
+            main = показать (Нет ());"#;
+
+        assert_ok(input);
+    }
+
+    #[test]
+    fn fail_unicode_names() {
+        // spans are counted in bytes and cover whole characters
+        let input = r#"число = 1; main = число ++ "ы";"#;
+        assert_err!(
+            input,
+            VerifyErr::TypeMismatch(span, ..)
+                if (span.offset(), span.len()) == (23, "число".len())
+        );
+    }
+
+    #[test]
+    fn ok_where() {
+        let inputs = [
+            r#"f 1 where { f x = if x > max then x else f (x + step); max = step * 4; step = 2; }"#,
+            r#"\n -> even n where { even n = n == 0 || odd (n - 1); odd n = n != 0 && even (n - 1); }"#,
+            r#"(a, b) where { (a, b) = (1, "s"); }"#,
+            r#"f x = if x > 0 then g x else h x where { g y = y; h y = 0 - y; }; main = f 1;"#,
+            r#"f x = case x of | 0 -> g x; | _ -> h x; where { g y = y; h y = y; }; main = f 1;"#,
+            r#"f x = g x + h x where { g y = y; h y = y; }; main = f 1;"#,
+        ];
+
+        for input in inputs {
+            assert_ok(input);
+        }
+    }
+
+    #[test]
+    fn fail_where() {
+        assert_err!(
+            r#"f 1 where { f x = f; }"#,
+            VerifyErr::InfiniteType(span, _) if (span.offset(), span.len()) == (12, 1)
+        );
+
+        assert_mismatch(r#"a where { a = b + 1; b = "s"; }"#);
+    }
 }
@@ -31,9 +31,16 @@ impl Cli {
         let ast = parse(&input)
             .map_err(|e| Err(miette::Error::from(e).with_source_code(input.clone())))?;
 
-        verify(&ast).map_err(|e| Err(miette::Error::from(e).with_source_code(input.clone())))?;
+        let verified = verify(&ast)
+            .map_err(|e| Err(miette::Error::from(e).with_source_code(input.clone())))?;
+        for warn in verified.warns {
+            eprintln!(
+                "{:?}",
+                miette::Error::from(warn).with_source_code(input.clone())
+            );
+        }
 
-        let exp = decay(&ast);
+        let exp = decay(&ast, &verified.double_negs);
         let code = compile(&exp);
 
         let file_bin = self.file.with_extension("bin");